@@ -0,0 +1,272 @@
+//! Splitting command-line arguments into options and values.
+
+use std::error::Error;
+use std::ffi::OsStr;
+use std::fmt;
+
+use crate::{encoded_bytes, from_encoded_bytes, OsStrExt2};
+
+/// A single event from an [`OsArgParser`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Arg<'a> {
+    /// A long option, like `--name` or `--name=value`.
+    Long(&'a str, Option<&'a OsStr>),
+    /// A short option, like `-n`. Clusters like `-abc` produce one event per option.
+    Short(char),
+    /// The value of the previous option, for options that were declared to take values.
+    Value(&'a OsStr),
+    /// An argument that isn't an option (or anything after `--`).
+    Positional(&'a OsStr),
+}
+
+/// An error from an [`OsArgParser`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ArgError<'a> {
+    /// The name of a long option (contained in this argument) wasn't valid Unicode.
+    InvalidLong(&'a OsStr),
+    /// A cluster of short options contained invalid Unicode. The rest of the cluster (starting
+    /// with the invalid option) is skipped.
+    InvalidShort(&'a OsStr),
+    /// The given option takes a value, but it was the last argument.
+    MissingValue(String),
+}
+
+impl fmt::Display for ArgError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidLong(arg) => write!(f, "invalid option {:?}", arg),
+            Self::InvalidShort(opts) => write!(f, "invalid option(s) {:?}", opts),
+            Self::MissingValue(opt) => write!(f, "option {} requires a value", opt),
+        }
+    }
+}
+
+impl Error for ArgError<'_> {}
+
+/// A lexer for command-line arguments.
+///
+/// This only splits arguments up; it doesn't know which options are valid. Options that take
+/// values must be declared with [`with_short_values()`](Self::with_short_values) and
+/// [`with_long_values()`](Self::with_long_values), so that the lexer knows to produce an
+/// [`Arg::Value`] for them (from the rest of a short option cluster, like `-ofile`, or from the
+/// next argument). A value given with `--name=value` is always included in the [`Arg::Long`].
+///
+/// Options and positional arguments can be mixed freely until a `--` argument, after which
+/// everything is positional. A lone `-` is positional.
+///
+/// Option names must be valid Unicode; values and positional arguments can be any `OsStr`.
+pub struct OsArgParser<'a> {
+    args: std::vec::IntoIter<&'a OsStr>,
+    // The remaining options in a short option cluster
+    cluster: Option<&'a OsStr>,
+    // The last option that was returned, if it needs a value
+    value_for: Option<String>,
+    opts_done: bool,
+    short_values: Vec<char>,
+    long_values: Vec<String>,
+}
+
+impl<'a> OsArgParser<'a> {
+    /// Create a parser for the given arguments (which should not include the program name).
+    pub fn new<S: AsRef<OsStr>>(args: &'a [S]) -> Self {
+        Self {
+            args: args
+                .iter()
+                .map(AsRef::as_ref)
+                .collect::<Vec<_>>()
+                .into_iter(),
+            cluster: None,
+            value_for: None,
+            opts_done: false,
+            short_values: Vec::new(),
+            long_values: Vec::new(),
+        }
+    }
+
+    /// Declare the short options (given as a string of option characters) that take values.
+    pub fn with_short_values(mut self, opts: &str) -> Self {
+        self.short_values.extend(opts.chars());
+        self
+    }
+
+    /// Declare the long options (given without the leading `--`) that take values.
+    pub fn with_long_values<S: AsRef<str>>(mut self, opts: &[S]) -> Self {
+        self.long_values
+            .extend(opts.iter().map(|opt| opt.as_ref().to_string()));
+        self
+    }
+
+    fn next_short(&mut self, cluster: &'a OsStr) -> Result<Arg<'a>, ArgError<'a>> {
+        let bytes = encoded_bytes(cluster);
+        let valid = match std::str::from_utf8(bytes) {
+            Ok(valid) => valid,
+            Err(e) => std::str::from_utf8(&bytes[..e.valid_up_to()]).unwrap(),
+        };
+
+        let ch = match valid.chars().next() {
+            Some(ch) => ch,
+            None => {
+                self.cluster = None;
+                return Err(ArgError::InvalidShort(cluster));
+            }
+        };
+
+        // This is a char boundary
+        self.cluster = Some(unsafe { from_encoded_bytes(&bytes[ch.len_utf8()..]) });
+        if self.short_values.contains(&ch) {
+            self.value_for = Some(format!("-{}", ch));
+        }
+
+        Ok(Arg::Short(ch))
+    }
+}
+
+impl<'a> Iterator for OsArgParser<'a> {
+    type Item = Result<Arg<'a>, ArgError<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(opt) = self.value_for.take() {
+            // Either the rest of a short option cluster, or the next argument
+            let value = match self.cluster.take() {
+                Some(rest) if !rest.is_empty() => Some(rest),
+                _ => self.args.next(),
+            };
+
+            return Some(value.map(Arg::Value).ok_or(ArgError::MissingValue(opt)));
+        }
+
+        match self.cluster {
+            Some(rest) if !rest.is_empty() => return Some(self.next_short(rest)),
+            _ => self.cluster = None,
+        }
+
+        let arg = self.args.next()?;
+
+        if self.opts_done {
+            return Some(Ok(Arg::Positional(arg)));
+        } else if arg == "--" {
+            self.opts_done = true;
+            return self.next();
+        }
+
+        if let Some(long) = arg.strip_prefix(OsStr::new("--")) {
+            let (name, value) = match long.split_once(OsStr::new("=")) {
+                Some((name, value)) => (name, Some(value)),
+                None => (long, None),
+            };
+
+            let name = match name.to_str() {
+                Some(name) => name,
+                None => return Some(Err(ArgError::InvalidLong(arg))),
+            };
+
+            if value.is_none() && self.long_values.iter().any(|opt| opt == name) {
+                self.value_for = Some(format!("--{}", name));
+            }
+
+            return Some(Ok(Arg::Long(name, value)));
+        }
+
+        match arg.strip_prefix(OsStr::new("-")) {
+            Some(cluster) if !cluster.is_empty() => Some(self.next_short(cluster)),
+            _ => Some(Ok(Arg::Positional(arg))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse<'a>(parser: OsArgParser<'a>) -> Vec<Result<Arg<'a>, ArgError<'a>>> {
+        parser.collect()
+    }
+
+    fn os(s: &str) -> &OsStr {
+        OsStr::new(s)
+    }
+
+    #[test]
+    fn test_basic() {
+        let args = [
+            "-a", "--bc", "x", "-", "--de=f=g", "--h=", "-ijk", "--", "-l", "--m",
+        ];
+
+        assert_eq!(
+            parse(OsArgParser::new(&args)),
+            [
+                Ok(Arg::Short('a')),
+                Ok(Arg::Long("bc", None)),
+                Ok(Arg::Positional(os("x"))),
+                Ok(Arg::Positional(os("-"))),
+                Ok(Arg::Long("de", Some(os("f=g")))),
+                Ok(Arg::Long("h", Some(os("")))),
+                Ok(Arg::Short('i')),
+                Ok(Arg::Short('j')),
+                Ok(Arg::Short('k')),
+                Ok(Arg::Positional(os("-l"))),
+                Ok(Arg::Positional(os("--m"))),
+            ]
+        );
+
+        assert_eq!(parse(OsArgParser::new::<&str>(&[])), []);
+        assert_eq!(parse(OsArgParser::new(&["--"])), []);
+        assert_eq!(parse(OsArgParser::new(&["-é"])), [Ok(Arg::Short('é'))]);
+    }
+
+    #[test]
+    fn test_values() {
+        let args = [
+            "-ofile", "-o", "-x", "-ao", "y", "--out", "--", "--out=z", "-o",
+        ];
+
+        assert_eq!(
+            parse(
+                OsArgParser::new(&args)
+                    .with_short_values("o")
+                    .with_long_values(&["out"])
+            ),
+            [
+                Ok(Arg::Short('o')),
+                Ok(Arg::Value(os("file"))),
+                Ok(Arg::Short('o')),
+                Ok(Arg::Value(os("-x"))),
+                Ok(Arg::Short('a')),
+                Ok(Arg::Short('o')),
+                Ok(Arg::Value(os("y"))),
+                Ok(Arg::Long("out", None)),
+                Ok(Arg::Value(os("--"))),
+                Ok(Arg::Long("out", Some(os("z")))),
+                Ok(Arg::Short('o')),
+                Err(ArgError::MissingValue("-o".into())),
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_invalid_unicode() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let args = [
+            OsStr::from_bytes(b"--a\xff"),
+            OsStr::from_bytes(b"--a=\xff"),
+            OsStr::from_bytes(b"-b\xffc"),
+            OsStr::from_bytes(b"-o\xff"),
+            OsStr::from_bytes(b"\xff"),
+        ];
+
+        assert_eq!(
+            parse(OsArgParser::new(&args).with_short_values("o")),
+            [
+                Err(ArgError::InvalidLong(args[0])),
+                Ok(Arg::Long("a", Some(OsStr::from_bytes(b"\xff")))),
+                Ok(Arg::Short('b')),
+                Err(ArgError::InvalidShort(OsStr::from_bytes(b"\xffc"))),
+                Ok(Arg::Short('o')),
+                Ok(Arg::Value(OsStr::from_bytes(b"\xff"))),
+                Ok(Arg::Positional(args[4])),
+            ]
+        );
+    }
+}
@@ -0,0 +1,214 @@
+//! Locale-aware comparison of `OsStr`s (like `ls` and `sort` do), with `strcoll()` and
+//! `strxfrm()`.
+//!
+//! C strings can't contain NULs, so strings are split at any NULs and compared a piece at a time
+//! (a string that runs out of pieces first sorts first).
+
+use std::cmp::Ordering;
+use std::ffi::{CStr, CString, OsStr};
+use std::io;
+use std::os::raw::{c_char, c_int};
+use std::os::unix::ffi::OsStrExt;
+
+use crate::with_c_str;
+
+// The libc crate only binds these on a few targets that we don't support
+extern "C" {
+    fn strcoll_l(s1: *const c_char, s2: *const c_char, locale: libc::locale_t) -> c_int;
+    fn strxfrm_l(
+        dest: *mut c_char,
+        src: *const c_char,
+        n: libc::size_t,
+        locale: libc::locale_t,
+    ) -> libc::size_t;
+}
+
+/// Compare two strings with `strcoll()`.
+///
+/// This uses the process's current `LC_COLLATE` locale, which is `C` (byte order) unless
+/// something has called `setlocale()`. To use the user's locale without touching global state,
+/// see [`Collator::from_env()`].
+#[inline]
+pub fn collate_cmp(a: &OsStr, b: &OsStr) -> Ordering {
+    compare_with(a, b, |a, b| unsafe {
+        libc::strcoll(a.as_ptr(), b.as_ptr())
+    })
+}
+
+/// Transform a string with `strxfrm()`, so that comparing the results byte-wise gives the same
+/// result as [`collate_cmp()`].
+#[inline]
+pub fn collation_key(s: &OsStr) -> Vec<u8> {
+    key_with(s, |dest, src, n| unsafe { libc::strxfrm(dest, src, n) })
+}
+
+/// Compares strings using a specific locale, independent of the process's current locale.
+#[derive(Debug)]
+pub struct Collator {
+    locale: libc::locale_t,
+}
+
+// Locale objects are never modified after they're created, so they can be used from any thread
+unsafe impl Send for Collator {}
+unsafe impl Sync for Collator {}
+
+impl Collator {
+    /// Load the collation rules for the named locale (for example, `C.UTF-8` or `en_US.UTF-8`).
+    pub fn new(locale: &str) -> io::Result<Self> {
+        let locale = CString::new(locale)?;
+        let locale = unsafe {
+            libc::newlocale(libc::LC_COLLATE_MASK, locale.as_ptr(), std::ptr::null_mut())
+        };
+
+        if locale.is_null() {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(Self { locale })
+        }
+    }
+
+    /// Load the collation rules for the locale specified by the environment (`LC_ALL`,
+    /// `LC_COLLATE` or `LANG`).
+    #[inline]
+    pub fn from_env() -> io::Result<Self> {
+        // An empty name means to look at the environment
+        Self::new("")
+    }
+
+    /// Like [`collate_cmp()`], but in this locale.
+    #[inline]
+    pub fn compare(&self, a: &OsStr, b: &OsStr) -> Ordering {
+        compare_with(a, b, |a, b| unsafe {
+            strcoll_l(a.as_ptr(), b.as_ptr(), self.locale)
+        })
+    }
+
+    /// Like [`collation_key()`], but in this locale.
+    #[inline]
+    pub fn key(&self, s: &OsStr) -> Vec<u8> {
+        key_with(s, |dest, src, n| unsafe {
+            strxfrm_l(dest, src, n, self.locale)
+        })
+    }
+}
+
+impl Drop for Collator {
+    fn drop(&mut self) {
+        unsafe {
+            libc::freelocale(self.locale);
+        }
+    }
+}
+
+fn compare_with<F: Fn(&CStr, &CStr) -> c_int>(a: &OsStr, b: &OsStr, coll: F) -> Ordering {
+    let mut a_pieces = a.as_bytes().split(|&ch| ch == 0);
+    let mut b_pieces = b.as_bytes().split(|&ch| ch == 0);
+
+    loop {
+        let (a, b) = match (a_pieces.next(), b_pieces.next()) {
+            (Some(a), Some(b)) => (OsStr::from_bytes(a), OsStr::from_bytes(b)),
+            (a, b) => return a.is_some().cmp(&b.is_some()),
+        };
+
+        // Neither of these can have NULs
+        let res = with_c_str(a, |a| with_c_str(b, |b| coll(a, b)).unwrap()).unwrap();
+        if res != 0 {
+            return res.cmp(&0);
+        }
+    }
+}
+
+fn key_with<F: Fn(*mut c_char, *const c_char, libc::size_t) -> libc::size_t>(
+    s: &OsStr,
+    xfrm: F,
+) -> Vec<u8> {
+    let mut key = Vec::with_capacity(s.len() * 2);
+
+    for (i, piece) in s.as_bytes().split(|&ch| ch == 0).enumerate() {
+        // This sorts before anything strxfrm() produces, so shorter strings still come first
+        if i > 0 {
+            key.push(0);
+        }
+
+        with_c_str(OsStr::from_bytes(piece), |piece| {
+            let start = key.len();
+            let mut size = piece.to_bytes().len() + 1;
+
+            loop {
+                key.resize(start + size, 0);
+                let len = xfrm(key[start..].as_mut_ptr().cast(), piece.as_ptr(), size);
+
+                if len < size {
+                    key.truncate(start + len);
+                    break;
+                }
+                // It didn't fit; the return value says how much space is needed
+                size = len + 1;
+            }
+        })
+        .unwrap();
+    }
+
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_sorted(collator: &Collator, strs: &[&[u8]]) {
+        for (i, a) in strs.iter().enumerate() {
+            for (j, b) in strs.iter().enumerate() {
+                let (a, b) = (OsStr::from_bytes(a), OsStr::from_bytes(b));
+                assert_eq!(collator.compare(a, b), i.cmp(&j), "{:?} {:?}", a, b);
+                assert_eq!(collator.key(a).cmp(&collator.key(b)), i.cmp(&j));
+            }
+        }
+    }
+
+    #[test]
+    fn test_collator() {
+        // Code point order in both of these (but `C.UTF-8` may only exist on Linux)
+        let locales: &[&str] = if cfg!(target_os = "linux") {
+            &["C", "C.UTF-8"]
+        } else {
+            &["C"]
+        };
+
+        for &locale in locales {
+            let collator = Collator::new(locale).unwrap();
+            check_sorted(
+                &collator,
+                &[
+                    b"",
+                    b"\0",
+                    b"\0\0",
+                    b"\0a",
+                    b"A",
+                    b"B",
+                    b"a",
+                    b"a\0",
+                    b"ab",
+                    b"b",
+                    b"\xc3\xa9",
+                ],
+            );
+        }
+
+        assert!(Collator::new("no_SUCH.locale").is_err());
+        assert!(Collator::new("C\0").is_err());
+    }
+
+    #[test]
+    fn test_global() {
+        // Nothing here calls setlocale(), so this is the C locale
+        let strs = ["", "A", "B", "a", "a\0", "a\0b", "b"];
+        for (i, a) in strs.iter().enumerate() {
+            for (j, b) in strs.iter().enumerate() {
+                let (a, b) = (OsStr::new(a), OsStr::new(b));
+                assert_eq!(collate_cmp(a, b), i.cmp(&j));
+                assert_eq!(collation_key(a).cmp(&collation_key(b)), i.cmp(&j));
+            }
+        }
+    }
+}
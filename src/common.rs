@@ -0,0 +1,226 @@
+//! Finding the longest common prefix or suffix of `OsStr`s.
+
+use std::ffi::OsStr;
+use std::path::is_separator;
+
+use crate::{common_prefix_bytes, common_suffix_bytes, encoded_bytes, from_encoded_bytes};
+
+/// Where a common prefix or suffix is allowed to end.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Boundary {
+    /// Anywhere. On Unix, this can split a UTF-8 sequence; on Windows, results always end at a
+    /// code point boundary.
+    Any,
+    /// Never in the middle of a UTF-8 sequence.
+    Char,
+    /// Prefixes are cut back to just after the last path separator in them, and suffixes are cut
+    /// to start just after the first path separator in them.
+    Separator,
+}
+
+/// Get the length of the longest common prefix of `a` and `b`.
+///
+/// The result is in the same units as [`OsStrExt2::find()`](crate::OsStrExt2::find).
+pub fn common_prefix_len(a: &OsStr, b: &OsStr) -> usize {
+    let a = encoded_bytes(a);
+    let n = common_prefix_bytes(a, encoded_bytes(b));
+    // This is a code point boundary
+    crate::unit_len(unsafe { from_encoded_bytes(&a[..n]) })
+}
+
+/// Get the longest prefix shared by all of the given strings (or an empty string if there are
+/// none).
+#[inline]
+pub fn common_prefix<'a, I, S>(strings: I) -> &'a OsStr
+where
+    I: IntoIterator<Item = &'a S>,
+    S: AsRef<OsStr> + ?Sized + 'a,
+{
+    common_prefix_at(strings, Boundary::Any)
+}
+
+/// Like [`common_prefix()`], but the result is cut back to end at the given kind of boundary.
+pub fn common_prefix_at<'a, I, S>(strings: I, boundary: Boundary) -> &'a OsStr
+where
+    I: IntoIterator<Item = &'a S>,
+    S: AsRef<OsStr> + ?Sized + 'a,
+{
+    let mut iter = strings.into_iter();
+    let mut prefix = match iter.next() {
+        Some(s) => encoded_bytes(s.as_ref()),
+        None => return OsStr::new(""),
+    };
+
+    for s in iter {
+        if prefix.is_empty() {
+            break;
+        }
+        prefix = &prefix[..common_prefix_bytes(prefix, encoded_bytes(s.as_ref()))];
+    }
+
+    let len = match boundary {
+        Boundary::Any => prefix.len(),
+        Boundary::Char => prefix.len() - incomplete_suffix_len(prefix),
+        Boundary::Separator => prefix
+            .iter()
+            .rposition(|&ch| is_separator_byte(ch))
+            .map_or(0, |i| i + 1),
+    };
+
+    // All of the cut points are code point boundaries
+    unsafe { from_encoded_bytes(&prefix[..len]) }
+}
+
+/// Get the longest suffix shared by all of the given strings (or an empty string if there are
+/// none).
+#[inline]
+pub fn common_suffix<'a, I, S>(strings: I) -> &'a OsStr
+where
+    I: IntoIterator<Item = &'a S>,
+    S: AsRef<OsStr> + ?Sized + 'a,
+{
+    common_suffix_at(strings, Boundary::Any)
+}
+
+/// Like [`common_suffix()`], but the result is cut to start at the given kind of boundary.
+pub fn common_suffix_at<'a, I, S>(strings: I, boundary: Boundary) -> &'a OsStr
+where
+    I: IntoIterator<Item = &'a S>,
+    S: AsRef<OsStr> + ?Sized + 'a,
+{
+    let mut iter = strings.into_iter();
+    let mut suffix = match iter.next() {
+        Some(s) => encoded_bytes(s.as_ref()),
+        None => return OsStr::new(""),
+    };
+
+    for s in iter {
+        if suffix.is_empty() {
+            break;
+        }
+        suffix = &suffix[suffix.len() - common_suffix_bytes(suffix, encoded_bytes(s.as_ref()))..];
+    }
+
+    let start = match boundary {
+        Boundary::Any => 0,
+        // Skip the end of a sequence that started before the suffix
+        Boundary::Char => suffix
+            .iter()
+            .take(3)
+            .take_while(|&&ch| ch & 0xc0 == 0x80)
+            .count(),
+        Boundary::Separator => suffix
+            .iter()
+            .position(|&ch| is_separator_byte(ch))
+            .map_or(suffix.len(), |i| i + 1),
+    };
+
+    // All of the cut points are code point boundaries
+    unsafe { from_encoded_bytes(&suffix[start..]) }
+}
+
+#[inline]
+fn is_separator_byte(ch: u8) -> bool {
+    ch.is_ascii() && is_separator(ch as char)
+}
+
+// Returns the length of an incomplete UTF-8 sequence at the end of `bytes`
+fn incomplete_suffix_len(bytes: &[u8]) -> usize {
+    for (n, &ch) in bytes.iter().rev().take(4).enumerate() {
+        if ch & 0xc0 == 0x80 {
+            continue;
+        }
+
+        let seq_len = match ch {
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf7 => 4,
+            _ => return 0,
+        };
+        return if n + 1 < seq_len { n + 1 } else { 0 };
+    }
+
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::OsString;
+
+    fn os(s: &str) -> &OsStr {
+        OsStr::new(s)
+    }
+
+    #[test]
+    fn test_common_prefix_len() {
+        assert_eq!(common_prefix_len(os(""), os("abc")), 0);
+        assert_eq!(common_prefix_len(os("abc"), os("abc")), 3);
+        assert_eq!(common_prefix_len(os("abc"), os("abd")), 2);
+        assert_eq!(common_prefix_len(os("abc"), os("ab")), 2);
+
+        // Long enough to exercise the word-at-a-time loop
+        let a = "0123456789abcdefghijklmnopqrstuvwxyz";
+        for i in 0..a.len() {
+            let mut b = a.to_string();
+            b.replace_range(i..i + 1, "_");
+            assert_eq!(common_prefix_len(os(a), os(&b)), i);
+            assert_eq!(common_suffix(&[a, &b]).len(), a.len() - i - 1);
+        }
+    }
+
+    #[test]
+    fn test_common_prefix() {
+        assert_eq!(common_prefix::<_, OsStr>(vec![]), "");
+        assert_eq!(common_prefix(&["abc"]), "abc");
+        assert_eq!(common_prefix(&["abc", "abd", "ab"]), "ab");
+        assert_eq!(common_prefix(&["abc", "", "ab"]), "");
+
+        let paths = vec![
+            OsString::from("/usr/lib/libfoo.so"),
+            OsString::from("/usr/lib/libfoo.a"),
+            OsString::from("/usr/lib64/libbar.so"),
+        ];
+        assert_eq!(common_prefix(&paths), "/usr/lib");
+        assert_eq!(common_prefix_at(&paths, Boundary::Separator), "/usr/");
+        assert_eq!(
+            common_prefix_at(&paths[..2], Boundary::Separator),
+            "/usr/lib/"
+        );
+        assert_eq!(common_prefix_at(&["a", "ab"], Boundary::Separator), "");
+
+        assert_eq!(common_prefix_at(&["café", "cafè"], Boundary::Char), "caf");
+        assert_eq!(common_prefix_at(&["日本", "日曜"], Boundary::Char), "日");
+    }
+
+    #[test]
+    fn test_common_suffix() {
+        assert_eq!(common_suffix::<_, OsStr>(vec![]), "");
+        assert_eq!(common_suffix(&["a.txt", "bb.txt", "c.txt"]), ".txt");
+        assert_eq!(common_suffix(&["a.txt", "txt"]), "txt");
+        assert_eq!(common_suffix(&["a.txt", "b.rs"]), "");
+
+        assert_eq!(
+            common_suffix_at(&["a/x/y.txt", "b/x/y.txt"], Boundary::Separator),
+            "x/y.txt"
+        );
+        assert_eq!(common_suffix_at(&["ax", "bx"], Boundary::Separator), "");
+
+        assert_eq!(common_suffix_at(&["éa", "èa"], Boundary::Char), "a");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_invalid_unicode() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let a = OsStr::from_bytes(b"x\xff\xc3\xa9");
+        let b = OsStr::from_bytes(b"x\xff\xc3\xa8");
+        assert_eq!(common_prefix_len(a, b), 3);
+        assert_eq!(common_prefix(&[a, b]), OsStr::from_bytes(b"x\xff\xc3"));
+        assert_eq!(
+            common_prefix_at(&[a, b], Boundary::Char),
+            OsStr::from_bytes(b"x\xff")
+        );
+    }
+}
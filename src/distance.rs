@@ -0,0 +1,225 @@
+//! Edit distances between `OsStr`s, and "did you mean" suggestions.
+//!
+//! Valid Unicode is compared a character at a time, and anything else a code unit at a time (so
+//! an invalid byte on Unix, or an unpaired surrogate on Windows, counts as one symbol).
+
+use std::ffi::OsStr;
+
+use crate::{for_each_chunk, Chunk, CodeUnit};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Symbol {
+    Char(char),
+    Unit(CodeUnit),
+}
+
+fn symbols(s: &OsStr) -> Vec<Symbol> {
+    let mut syms = Vec::with_capacity(s.len());
+    for_each_chunk(s, |chunk| match chunk {
+        Chunk::Valid(s) => syms.extend(s.chars().map(Symbol::Char)),
+        Chunk::Invalid(units) => syms.extend(units.iter().copied().map(Symbol::Unit)),
+    });
+    syms
+}
+
+/// Get the Levenshtein distance between `a` and `b` (the number of insertions, deletions and
+/// substitutions needed to turn one into the other).
+pub fn levenshtein(a: &OsStr, b: &OsStr) -> usize {
+    edit_distance(&symbols(a), &symbols(b), false, usize::MAX).unwrap()
+}
+
+/// Like [`levenshtein()`], but swapping two adjacent symbols also counts as a single edit.
+///
+/// This is the "optimal string alignment" variant, so a substring can't be edited again after
+/// it's been swapped.
+pub fn damerau_levenshtein(a: &OsStr, b: &OsStr) -> usize {
+    edit_distance(&symbols(a), &symbols(b), true, usize::MAX).unwrap()
+}
+
+/// Check whether the Levenshtein distance between `a` and `b` is at most `k`.
+///
+/// This returns `false` straight away if the lengths differ by more than `k`, and otherwise only
+/// looks at edits that stay within `k` of the diagonal, so it takes `O(k * n)` time rather than
+/// the `O(n * m)` of [`levenshtein()`].
+pub fn within_distance(a: &OsStr, b: &OsStr, k: usize) -> bool {
+    edit_distance(&symbols(a), &symbols(b), false, k).is_some()
+}
+
+/// Find the candidates that are within a Damerau-Levenshtein distance of `max_distance` from
+/// `input`, closest first.
+///
+/// Candidates at the same distance are kept in their original order.
+pub fn suggest<'a, I, S>(input: &OsStr, candidates: I, max_distance: usize) -> Vec<&'a OsStr>
+where
+    I: IntoIterator<Item = &'a S>,
+    S: AsRef<OsStr> + ?Sized + 'a,
+{
+    let input = symbols(input);
+
+    let mut matches: Vec<(usize, &OsStr)> = candidates
+        .into_iter()
+        .filter_map(|candidate| {
+            let candidate = candidate.as_ref();
+            edit_distance(&input, &symbols(candidate), true, max_distance)
+                .map(|dist| (dist, candidate))
+        })
+        .collect();
+
+    // This sort is stable
+    matches.sort_by_key(|&(dist, _)| dist);
+    matches
+        .into_iter()
+        .map(|(_, candidate)| candidate)
+        .collect()
+}
+
+// Returns `None` if the distance is larger than `max`
+fn edit_distance(a: &[Symbol], b: &[Symbol], transpose: bool, max: usize) -> Option<usize> {
+    let (a, b) = if a.len() < b.len() { (b, a) } else { (a, b) };
+    if a.len() - b.len() > max {
+        return None;
+    }
+
+    // Any cell more than `max` away from the diagonal is more than `max` from the start, so only
+    // the band around the diagonal is computed (Ukkonen's cutoff). The cells just outside it are
+    // set to `over`, which is all that matters about them.
+    let over = max.saturating_add(1);
+    let edge = |i: usize| if i <= max { i } else { over };
+
+    // Rows of the DP table, indexed by position in `b`
+    let mut prev2 = vec![over; b.len() + 1];
+    let mut prev: Vec<usize> = (0..=b.len()).map(edge).collect();
+    let mut cur = vec![over; b.len() + 1];
+    let mut prev_min = 0;
+
+    for i in 1..=a.len() {
+        let lo = i.saturating_sub(max).max(1);
+        let hi = i.saturating_add(max).min(b.len());
+
+        cur[0] = edge(i);
+        if lo > 1 {
+            cur[lo - 1] = over;
+        }
+        let mut row_min = cur[0];
+
+        for j in lo..=hi {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            let mut dist = prev[j - 1]
+                .saturating_add(cost)
+                .min(prev[j].saturating_add(1))
+                .min(cur[j - 1].saturating_add(1));
+
+            if transpose && i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                dist = dist.min(prev2[j - 2].saturating_add(1));
+            }
+
+            cur[j] = dist.min(over);
+            row_min = row_min.min(cur[j]);
+        }
+
+        if hi < b.len() {
+            cur[hi + 1] = over;
+        }
+
+        // Later rows can't go below the minimum of this row (or of the row before it, plus one
+        // for a transposition), so give up early
+        if row_min > max && (!transpose || prev_min > max) {
+            return None;
+        }
+
+        std::mem::swap(&mut prev2, &mut prev);
+        std::mem::swap(&mut prev, &mut cur);
+        prev_min = row_min;
+    }
+
+    Some(prev[b.len()]).filter(|&dist| dist <= max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn os(s: &str) -> &OsStr {
+        OsStr::new(s)
+    }
+
+    #[test]
+    fn test_levenshtein() {
+        assert_eq!(levenshtein(os(""), os("")), 0);
+        assert_eq!(levenshtein(os(""), os("abc")), 3);
+        assert_eq!(levenshtein(os("kitten"), os("sitting")), 3);
+        assert_eq!(levenshtein(os("sitting"), os("kitten")), 3);
+        assert_eq!(levenshtein(os("ab"), os("ba")), 2);
+        assert_eq!(levenshtein(os("café"), os("cafe")), 1);
+
+        assert_eq!(damerau_levenshtein(os("ab"), os("ba")), 1);
+        assert_eq!(damerau_levenshtein(os("abcd"), os("acbd")), 1);
+        assert_eq!(damerau_levenshtein(os("ca"), os("abc")), 3);
+        assert_eq!(damerau_levenshtein(os("kitten"), os("sitting")), 3);
+    }
+
+    #[test]
+    fn test_within_distance() {
+        assert!(within_distance(os("kitten"), os("sitting"), 3));
+        assert!(!within_distance(os("kitten"), os("sitting"), 2));
+        assert!(within_distance(os("abc"), os("abc"), 0));
+        assert!(!within_distance(os("a"), os("abcdef"), 4));
+        assert!(!within_distance(os("abcdef"), os("uvwxyz"), 5));
+        assert!(within_distance(os(""), os(""), 0));
+
+        // The banded search agrees with the full table
+        let mut strs = vec![String::new()];
+        for len in 1..=4 {
+            let start = strs.len() - 3usize.pow(len - 1);
+            for i in start..strs.len() {
+                for ch in ["a", "b", "c"].iter() {
+                    strs.push(format!("{}{}", strs[i], ch));
+                }
+            }
+        }
+
+        for a in strs.iter() {
+            for b in strs.iter() {
+                let (a, b) = (symbols(os(a)), symbols(os(b)));
+                for &transpose in [false, true].iter() {
+                    let full = edit_distance(&a, &b, transpose, usize::MAX).unwrap();
+                    for k in 0..=4 {
+                        let expected = Some(full).filter(|&dist| dist <= k);
+                        assert_eq!(edit_distance(&a, &b, transpose, k), expected);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_suggest() {
+        let commands = ["status", "stash", "commit", "checkout", "stats"];
+
+        assert_eq!(suggest(os("stauts"), &commands, 2), ["status", "stats"]);
+        assert_eq!(
+            suggest(os("stat"), &commands, 2),
+            ["stats", "status", "stash"]
+        );
+        assert_eq!(suggest(os("comit"), &commands, 1), ["commit"]);
+        assert_eq!(suggest(os("xyz"), &commands, 2), [] as [&OsStr; 0]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_invalid_unicode() {
+        use std::os::unix::ffi::OsStrExt;
+
+        // "é" is one symbol, but each invalid byte is one symbol too
+        assert_eq!(levenshtein(os("é"), OsStr::from_bytes(b"\xff")), 1);
+        assert_eq!(levenshtein(os("é"), OsStr::from_bytes(b"\xc3")), 1);
+        assert_eq!(levenshtein(os("a"), OsStr::from_bytes(b"\xff\xfe")), 2);
+        assert_eq!(
+            damerau_levenshtein(OsStr::from_bytes(b"\xffa"), OsStr::from_bytes(b"a\xff")),
+            1
+        );
+
+        let files = [OsStr::from_bytes(b"caf\xe9.txt"), os("other.txt")];
+        assert_eq!(suggest(os("cafe.txt"), &files, 1), [files[0]]);
+    }
+}
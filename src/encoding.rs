@@ -0,0 +1,224 @@
+//! Decoding and encoding `OsStr`s in legacy encodings (Latin-1/Windows-1252, Shift_JIS, GBK, ...)
+//! with `encoding_rs`.
+//!
+//! [`decode_as()`] and [`encode_from()`] return the converted string along with the ranges that
+//! couldn't be converted, as [`Decoded`] and [`Encoded`]. The error ranges are byte offsets into
+//! an `OsStr` (the input for [`Decoded`], and the output for [`Encoded`]), so they're compatible
+//! with [`OsStrExt2::find()`](crate::OsStrExt2::find) and
+//! [`OsStrExt2::substr()`](crate::OsStrExt2::substr).
+
+use std::borrow::Cow;
+use std::ffi::{OsStr, OsString};
+use std::ops::Range;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+use encoding_rs::{DecoderResult, EncoderResult};
+
+pub use encoding_rs::Encoding;
+
+/// The result of [`decode_as()`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Decoded<'a> {
+    pub text: Cow<'a, str>,
+    /// The malformed byte sequences in the input, each of which was replaced with U+FFFD in
+    /// `text`.
+    pub errors: Vec<Range<usize>>,
+}
+
+/// The result of [`encode_from()`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Encoded {
+    pub os_string: OsString,
+    /// The ranges of the output where characters that couldn't be encoded were replaced with `?`.
+    pub errors: Vec<Range<usize>>,
+}
+
+/// Decode `s` from `encoding`. Byte order marks are not treated specially.
+pub fn decode_as<'a>(s: &'a OsStr, encoding: &'static Encoding) -> Decoded<'a> {
+    let bytes = s.as_bytes();
+
+    let (text, had_errors) = encoding.decode_without_bom_handling(bytes);
+    if !had_errors {
+        return Decoded {
+            text,
+            errors: Vec::new(),
+        };
+    }
+
+    // Go back through it to find exactly where the errors were
+    let mut decoder = encoding.new_decoder_without_bom_handling();
+    let mut text = String::with_capacity(text.len());
+    let mut errors = Vec::new();
+    let mut pos = 0;
+
+    loop {
+        let (res, nread) =
+            decoder.decode_to_string_without_replacement(&bytes[pos..], &mut text, true);
+        pos += nread;
+
+        match res {
+            DecoderResult::InputEmpty => break,
+
+            DecoderResult::OutputFull => text.reserve(
+                decoder
+                    .max_utf8_buffer_length_without_replacement(bytes.len() - pos)
+                    .unwrap_or(bytes.len() - pos),
+            ),
+
+            // The bad sequence ends `extra` bytes before the current position
+            DecoderResult::Malformed(bad, extra) => {
+                let end = pos - usize::from(extra);
+                errors.push(end - usize::from(bad)..end);
+                text.push(char::REPLACEMENT_CHARACTER);
+            }
+        }
+    }
+
+    Decoded {
+        text: Cow::Owned(text),
+        errors,
+    }
+}
+
+/// Encode `s` in `encoding`.
+///
+/// Like `encoding_rs`, this can't encode to UTF-16; UTF-16LE and UTF-16BE produce UTF-8 instead.
+pub fn encode_from(s: &str, encoding: &'static Encoding) -> Encoded {
+    let mut encoder = encoding.new_encoder();
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut errors = Vec::new();
+    let mut pos = 0;
+
+    loop {
+        let (res, nread) =
+            encoder.encode_from_utf8_to_vec_without_replacement(&s[pos..], &mut bytes, true);
+        pos += nread;
+
+        match res {
+            EncoderResult::InputEmpty => break,
+
+            EncoderResult::OutputFull => bytes.reserve(
+                encoder
+                    .max_buffer_length_from_utf8_without_replacement(s.len() - pos)
+                    .unwrap_or(s.len() - pos),
+            ),
+
+            EncoderResult::Unmappable(_) => {
+                errors.push(bytes.len()..bytes.len() + 1);
+                bytes.push(b'?');
+            }
+        }
+    }
+
+    Encoded {
+        os_string: OsString::from_vec(bytes),
+        errors,
+    }
+}
+
+// Tried in order; ties go to the earliest one. Windows-1252 maps every byte, so it always works as
+// a last resort.
+static CANDIDATES: &[&Encoding] = &[
+    encoding_rs::UTF_8,
+    encoding_rs::SHIFT_JIS,
+    encoding_rs::EUC_JP,
+    encoding_rs::GBK,
+    encoding_rs::BIG5,
+    encoding_rs::EUC_KR,
+    encoding_rs::WINDOWS_1252,
+];
+
+/// Guess which encoding `s` is in, and decode it.
+///
+/// This is a heuristic: it picks the common encoding that decodes `s` with the fewest errors
+/// (preferring UTF-8, then the CJK multibyte encodings, then Windows-1252) and control
+/// characters. Short strings are often valid in several encodings, so the guess may be wrong.
+pub fn guess_encoding(s: &OsStr) -> (&'static Encoding, Decoded<'_>) {
+    let mut best: Option<(&'static Encoding, Decoded, usize)> = None;
+
+    for &encoding in CANDIDATES {
+        let decoded = decode_as(s, encoding);
+
+        let controls = decoded
+            .text
+            .chars()
+            .filter(|ch| ch.is_control() && !ch.is_ascii_control())
+            .count();
+        let badness = decoded.errors.len() + controls;
+
+        if best.as_ref().map_or(true, |(_, _, best)| badness < *best) {
+            let done = badness == 0;
+            best = Some((encoding, decoded, badness));
+            if done {
+                break;
+            }
+        }
+    }
+
+    let (encoding, decoded, _) = best.unwrap();
+    (encoding, decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::OsStrExt2;
+    use encoding_rs::{SHIFT_JIS, UTF_8, WINDOWS_1252};
+
+    #[test]
+    fn test_decode_as() {
+        let s = OsStr::from_bytes(b"caf\xe9");
+        let decoded = decode_as(s, WINDOWS_1252);
+        assert_eq!(decoded.text, "caf\u{e9}");
+        assert!(decoded.errors.is_empty());
+
+        let decoded = decode_as(OsStr::new("abc"), SHIFT_JIS);
+        assert!(matches!(decoded.text, Cow::Borrowed("abc")));
+
+        // "日本" in Shift_JIS, with a truncated sequence and a stray byte
+        let s = OsStr::from_bytes(b"\x93\xfa\x96\x7b\xff/\x93");
+        let decoded = decode_as(s, SHIFT_JIS);
+        assert_eq!(decoded.text, "日本\u{fffd}/\u{fffd}");
+        assert_eq!(decoded.errors, [4..5, 6..7]);
+        assert_eq!(s.substr(4, 5), OsStr::from_bytes(b"\xff"));
+        assert_eq!(s.find(OsStr::new("/")), Some(5));
+
+        let decoded = decode_as(OsStr::from_bytes(b"a\xe9b"), UTF_8);
+        assert_eq!(decoded.text, "a\u{fffd}b");
+        assert_eq!(decoded.errors.len(), 1);
+        assert_eq!(decoded.errors[0], 1..2);
+    }
+
+    #[test]
+    fn test_encode_from() {
+        let encoded = encode_from("caf\u{e9}", WINDOWS_1252);
+        assert_eq!(encoded.os_string, OsStr::from_bytes(b"caf\xe9"));
+        assert!(encoded.errors.is_empty());
+
+        let encoded = encode_from("日本/\u{e9}", SHIFT_JIS);
+        assert_eq!(encoded.os_string, OsStr::from_bytes(b"\x93\xfa\x96\x7b/?"));
+        assert_eq!(encoded.errors.len(), 1);
+        assert_eq!(encoded.errors[0], 5..6);
+
+        let encoded = encode_from("\u{1f600}x\u{1f600}", WINDOWS_1252);
+        assert_eq!(encoded.os_string, "?x?");
+        assert_eq!(encoded.errors, [0..1, 2..3]);
+    }
+
+    #[test]
+    fn test_guess_encoding() {
+        let (encoding, decoded) = guess_encoding(OsStr::new("日本"));
+        assert_eq!(encoding, UTF_8);
+        assert_eq!(decoded.text, "日本");
+
+        let s = encode_from("日本語のファイル", SHIFT_JIS).os_string;
+        let (encoding, decoded) = guess_encoding(&s);
+        assert_eq!(encoding, SHIFT_JIS);
+        assert_eq!(decoded.text, "日本語のファイル");
+
+        let (encoding, decoded) = guess_encoding(OsStr::from_bytes(b"na\xefve caf\xe9"));
+        assert_eq!(encoding, WINDOWS_1252);
+        assert_eq!(decoded.text, "na\u{ef}ve caf\u{e9}");
+        assert!(decoded.errors.is_empty());
+    }
+}
@@ -0,0 +1,384 @@
+//! Editing environment blocks that may not be valid Unicode.
+
+use std::ffi::{OsStr, OsString};
+
+#[cfg(unix)]
+use crate::nul_separated::CStringArray;
+use crate::{for_each_chunk, push_str_units, unit_len, Chunk, CodeUnit, OsStrExt2};
+#[cfg(unix)]
+use std::ffi::{CString, NulError};
+#[cfg(unix)]
+use std::os::unix::ffi::OsStringExt;
+
+/// How keys in an [`OsEnv`] are compared.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum KeyMode {
+    /// Keys are compared exactly (the Unix behavior).
+    CaseSensitive,
+    /// Keys are compared case-insensitively (the Windows behavior).
+    CaseInsensitive,
+}
+
+impl Default for KeyMode {
+    /// The behavior of the current platform.
+    #[inline]
+    fn default() -> Self {
+        if cfg!(windows) {
+            Self::CaseInsensitive
+        } else {
+            Self::CaseSensitive
+        }
+    }
+}
+
+/// An ordered set of environment variables.
+///
+/// Variables are kept in the order they were first added; replacing the value of an existing
+/// variable doesn't change its position.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct OsEnv {
+    vars: Vec<(OsString, OsString)>,
+    mode: KeyMode,
+}
+
+impl OsEnv {
+    #[inline]
+    pub fn new(mode: KeyMode) -> Self {
+        Self {
+            vars: Vec::new(),
+            mode,
+        }
+    }
+
+    /// Parse a list of `KEY=VALUE` entries.
+    ///
+    /// Entries without an `=` (after the first character) are ignored. If a key is repeated, the
+    /// last value wins.
+    pub fn from_entries<I, S>(mode: KeyMode, entries: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let mut env = Self::new(mode);
+        for entry in entries {
+            env.set_entry(entry.as_ref());
+        }
+        env
+    }
+
+    /// Take a snapshot of the current process's environment.
+    ///
+    /// Variables whose names couldn't be passed to [`set()`](Self::set) (for example, `=`, which
+    /// a process can be started with) are skipped.
+    pub fn from_current() -> Self {
+        let mut env = Self::default();
+        for (key, value) in std::env::vars_os() {
+            if is_valid_key(&key) {
+                env.set(&key, &value);
+            }
+        }
+        env
+    }
+
+    #[inline]
+    pub fn mode(&self) -> KeyMode {
+        self.mode
+    }
+
+    /// Parse a single `KEY=VALUE` entry and add it, returning `false` if it has no `=` or the key
+    /// is invalid (see [`set()`](Self::set)).
+    pub fn set_entry(&mut self, entry: &OsStr) -> bool {
+        let eq = OsStr::new("=");
+
+        let index = if entry.starts_with(eq) {
+            // Keys can't be empty, so a leading "=" is part of the key (Windows uses this for
+            // special variables like "=C:")
+            entry.find_all(eq).nth(1)
+        } else {
+            entry.find(eq)
+        };
+
+        match index {
+            Some(index) => {
+                let key = entry.substr(0, index);
+                if !is_valid_key(&key) {
+                    return false;
+                }
+
+                let value = entry.substr(index + 1, unit_len(entry));
+                self.set(&key, &value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn position(&self, key: &OsStr) -> Option<usize> {
+        match self.mode {
+            KeyMode::CaseSensitive => self.vars.iter().position(|(k, _)| k == key),
+            KeyMode::CaseInsensitive => {
+                let key = fold_case(key);
+                self.vars.iter().position(|(k, _)| fold_case(k) == key)
+            }
+        }
+    }
+
+    pub fn get(&self, key: &OsStr) -> Option<&OsStr> {
+        self.position(key).map(|i| self.vars[i].1.as_os_str())
+    }
+
+    #[inline]
+    pub fn contains_key(&self, key: &OsStr) -> bool {
+        self.position(key).is_some()
+    }
+
+    /// Set the value of a variable, returning the old value (if any).
+    ///
+    /// When replacing a variable, the original spelling of the key is kept.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `key` is empty or contains an `=` anywhere but at the start.
+    pub fn set(&mut self, key: &OsStr, value: &OsStr) -> Option<OsString> {
+        assert!(
+            is_valid_key(key),
+            "invalid environment variable name {:?}",
+            key
+        );
+
+        match self.position(key) {
+            Some(i) => Some(std::mem::replace(&mut self.vars[i].1, value.into())),
+            None => {
+                self.vars.push((key.into(), value.into()));
+                None
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &OsStr) -> Option<OsString> {
+        self.position(key).map(|i| self.vars.remove(i).1)
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.vars.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.vars.is_empty()
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.vars.clear();
+    }
+
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = (&OsStr, &OsStr)> + '_ {
+        self.vars
+            .iter()
+            .map(|(k, v)| (k.as_os_str(), v.as_os_str()))
+    }
+
+    /// Sort the variables by key.
+    ///
+    /// In [`KeyMode::CaseInsensitive`], the keys are sorted case-insensitively (which is the
+    /// order Windows expects environment blocks to be in).
+    pub fn sort(&mut self) {
+        match self.mode {
+            KeyMode::CaseSensitive => self.vars.sort_by(|(a, _), (b, _)| a.cmp(b)),
+            KeyMode::CaseInsensitive => self.vars.sort_by_cached_key(|(k, _)| fold_case(k)),
+        }
+    }
+
+    /// Build the list of `KEY=VALUE` entries.
+    pub fn to_entries(&self) -> Vec<OsString> {
+        self.iter()
+            .map(|(key, value)| {
+                let mut entry = OsString::with_capacity(key.len() + value.len() + 1);
+                entry.push(key);
+                entry.push("=");
+                entry.push(value);
+                entry
+            })
+            .collect()
+    }
+
+    /// Build a NUL-separated block of `KEY=VALUE` entries (like `/proc/<pid>/environ`).
+    ///
+    /// Fails if any of the keys or values contains a NUL. The error's
+    /// [`nul_position()`](NulError::nul_position) and bytes are those of the `KEY=VALUE` entry
+    /// that contains it, not of the whole block.
+    #[cfg(unix)]
+    pub fn to_nul_separated(&self) -> Result<Vec<u8>, NulError> {
+        let mut buf = Vec::new();
+        for entry in self.to_entries() {
+            buf.extend_from_slice(CString::new(entry.into_vec())?.as_bytes_with_nul());
+        }
+        Ok(buf)
+    }
+
+    /// Build an `envp`-style array of `KEY=VALUE` entries.
+    ///
+    /// Fails if any of the keys or values contains a NUL.
+    #[cfg(unix)]
+    pub fn to_cstring_array(&self) -> Result<CStringArray, NulError> {
+        let mut arr = CStringArray::with_capacity(self.len());
+        for entry in self.to_entries() {
+            arr.push(&entry)?;
+        }
+        Ok(arr)
+    }
+}
+
+/// Variables with invalid names (which [`OsEnv::set()`] would panic on) are skipped.
+impl<K: AsRef<OsStr>, V: AsRef<OsStr>> Extend<(K, V)> for OsEnv {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            let key = key.as_ref();
+            if is_valid_key(key) {
+                self.set(key, value.as_ref());
+            }
+        }
+    }
+}
+
+// Keys can't be empty, and can only contain an `=` as their first character
+fn is_valid_key(key: &OsStr) -> bool {
+    let eq = OsStr::new("=");
+    let skip = if key.starts_with(eq) { 1 } else { 0 };
+    unit_len(key) > skip && key.find_all(eq).nth(skip).is_none()
+}
+
+// Windows uses simple (one-to-one) uppercase mappings to compare variable names
+fn fold_case(s: &OsStr) -> Vec<CodeUnit> {
+    let mut units = Vec::new();
+
+    for_each_chunk(s, |chunk| match chunk {
+        Chunk::Valid(s) => {
+            let mut buf = [0; 4];
+            for ch in s.chars() {
+                let mut upper = ch.to_uppercase();
+                let ch = match (upper.next(), upper.next()) {
+                    (Some(u), None) => u,
+                    _ => ch,
+                };
+                push_str_units(&mut units, ch.encode_utf8(&mut buf));
+            }
+        }
+        Chunk::Invalid(invalid) => units.extend_from_slice(invalid),
+    });
+
+    units
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let env = OsEnv::from_entries(
+            KeyMode::CaseSensitive,
+            ["A=1", "B=x=y", "junk", "=", "C=", "A=2", "=D=4"].iter(),
+        );
+
+        assert_eq!(
+            env.iter().collect::<Vec<_>>(),
+            [
+                (OsStr::new("A"), OsStr::new("2")),
+                (OsStr::new("B"), OsStr::new("x=y")),
+                (OsStr::new("C"), OsStr::new("")),
+                (OsStr::new("=D"), OsStr::new("4")),
+            ]
+        );
+
+        // These would give a key of "=", which isn't allowed
+        let mut env = OsEnv::new(KeyMode::CaseSensitive);
+        for &entry in ["=", "==", "==x"].iter() {
+            assert!(!env.set_entry(OsStr::new(entry)));
+        }
+        assert!(env.is_empty());
+        assert!(env.set_entry(OsStr::new("=x==")));
+        assert_eq!(env.get(OsStr::new("=x")), Some(OsStr::new("=")));
+
+        let env = OsEnv::from_entries(KeyMode::CaseInsensitive, ["=C:=C:\\x", "a=1"].iter());
+        assert_eq!(env.get(OsStr::new("=c:")), Some(OsStr::new("C:\\x")));
+        assert_eq!(env.get(OsStr::new("A")), Some(OsStr::new("1")));
+    }
+
+    #[test]
+    fn test_edit() {
+        let mut env = OsEnv::new(KeyMode::CaseSensitive);
+        assert!(env.is_empty());
+
+        assert_eq!(env.set(OsStr::new("PATH"), OsStr::new("/bin")), None);
+        assert_eq!(env.set(OsStr::new("path"), OsStr::new("/usr/bin")), None);
+        assert_eq!(env.set(OsStr::new("HOME"), OsStr::new("/root")), None);
+        assert_eq!(
+            env.set(OsStr::new("PATH"), OsStr::new("/sbin")),
+            Some("/bin".into())
+        );
+        assert_eq!(env.len(), 3);
+
+        assert_eq!(env.get(OsStr::new("PATH")), Some(OsStr::new("/sbin")));
+        assert_eq!(env.get(OsStr::new("Path")), None);
+        assert_eq!(env.remove(OsStr::new("path")), Some("/usr/bin".into()));
+        assert_eq!(env.remove(OsStr::new("path")), None);
+
+        assert_eq!(env.to_entries(), ["PATH=/sbin", "HOME=/root"]);
+        env.sort();
+        assert_eq!(env.to_entries(), ["HOME=/root", "PATH=/sbin"]);
+    }
+
+    #[test]
+    fn test_case_insensitive() {
+        let mut env = OsEnv::new(KeyMode::CaseInsensitive);
+
+        assert_eq!(env.set(OsStr::new("Path"), OsStr::new("a")), None);
+        assert_eq!(
+            env.set(OsStr::new("PATH"), OsStr::new("b")),
+            Some("a".into())
+        );
+        assert_eq!(env.set(OsStr::new("éa"), OsStr::new("c")), None);
+        assert_eq!(env.get(OsStr::new("path")), Some(OsStr::new("b")));
+        assert_eq!(env.get(OsStr::new("ÉA")), Some(OsStr::new("c")));
+        assert_eq!(env.to_entries(), ["Path=b", "éa=c"]);
+
+        env.set(OsStr::new("b"), OsStr::new(""));
+        env.sort();
+        assert_eq!(env.to_entries(), ["b=", "Path=b", "éa=c"]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_set_invalid() {
+        OsEnv::new(KeyMode::CaseSensitive).set(OsStr::new("A=B"), OsStr::new(""));
+    }
+
+    #[test]
+    fn test_extend() {
+        let mut env = OsEnv::new(KeyMode::CaseSensitive);
+        env.extend(vec![("A", "1"), ("", "2"), ("B=C", "3"), ("=D", "4")]);
+        assert_eq!(env.to_entries(), ["A=1", "=D=4"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_to_nul_separated() {
+        let mut env = OsEnv::from_entries(KeyMode::CaseSensitive, ["A=1", "B=2"].iter());
+        assert_eq!(env.to_nul_separated().unwrap(), b"A=1\0B=2\0");
+
+        let arr = env.to_cstring_array().unwrap();
+        assert_eq!(
+            arr.iter().map(|s| s.to_bytes()).collect::<Vec<_>>(),
+            [b"A=1", b"B=2"]
+        );
+
+        env.set(OsStr::new("C"), OsStr::new("\0"));
+        // The position is within the "C=\0" entry
+        assert_eq!(env.to_nul_separated().unwrap_err().nul_position(), 2);
+        assert_eq!(env.to_cstring_array().unwrap_err().nul_position(), 2);
+    }
+}
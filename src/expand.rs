@@ -0,0 +1,330 @@
+//! Shell-style variable expansion in strings that may not be valid Unicode.
+
+use std::error::Error;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::path::is_separator;
+
+use crate::{code_units, from_code_units, unit_to_ascii, CodeUnit, OsStrExt2};
+
+/// An error from [`expand_vars()`].
+///
+/// Positions are in the same units as [`OsStrExt2::find()`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ExpandError {
+    /// A `${` was never closed.
+    Unterminated { position: usize },
+    /// A `${...}` had an empty or invalid variable name, or an unknown operator.
+    BadSubstitution { position: usize },
+    /// A `${NAME:?message}` referred to a variable that was unset or empty.
+    Unset { name: String, message: OsString },
+}
+
+impl fmt::Display for ExpandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Unterminated { position } => {
+                write!(f, "unterminated variable reference at {}", position)
+            }
+            Self::BadSubstitution { position } => write!(f, "bad substitution at {}", position),
+            Self::Unset { name, message } if message.is_empty() => {
+                write!(f, "{}: parameter null or not set", name)
+            }
+            Self::Unset { name, message } => {
+                write!(f, "{}: {}", name, message.to_string_lossy())
+            }
+        }
+    }
+}
+
+impl Error for ExpandError {}
+
+/// Expand environment variable references in `s`, using `lookup` to get variable values.
+///
+/// The following forms are supported:
+///
+/// - `$NAME` and `${NAME}`: the value of `NAME`, or nothing if it's unset.
+/// - `${NAME:-default}`: the value of `NAME`, or `default` (which is expanded in turn) if it's
+///   unset or empty.
+/// - `${NAME:?message}`: the value of `NAME`; fails with [`ExpandError::Unset`] if it's unset or
+///   empty.
+/// - `$$`: a literal `$`.
+/// - A leading `~` on its own or followed by a path separator: the value of `HOME` (if it's set).
+///
+/// Names consist of ASCII letters, digits and underscores, and can't start with a digit. A `$`
+/// that isn't followed by one of these forms is left as is, as is everything between references.
+pub fn expand_vars<F>(s: &OsStr, lookup: F) -> Result<OsString, ExpandError>
+where
+    F: Fn(&OsStr) -> Option<OsString>,
+{
+    let mut out = Vec::new();
+    expand_into(s, 0, &lookup, &mut out)?;
+    Ok(from_code_units(out))
+}
+
+// `base` is the position of `s` in the original string, for error reporting
+fn expand_into(
+    s: &OsStr,
+    base: usize,
+    lookup: &dyn Fn(&OsStr) -> Option<OsString>,
+    out: &mut Vec<CodeUnit>,
+) -> Result<(), ExpandError> {
+    let units = code_units(s);
+    out.reserve(units.len());
+
+    let mut pos = 0;
+
+    if units.first() == Some(&unit(b'~'))
+        && (units.len() == 1 || unit_to_ascii(units[1]).is_some_and(|ch| is_separator(ch as char)))
+    {
+        if let Some(home) = lookup(OsStr::new("HOME")) {
+            out.extend_from_slice(&code_units(&home));
+            pos = 1;
+        }
+    }
+
+    for dollar in s.find_all(OsStr::new("$")) {
+        if dollar < pos {
+            // Part of a reference we already expanded
+            continue;
+        }
+
+        out.extend_from_slice(&units[pos..dollar]);
+        pos = expand_ref(&units, dollar, base, lookup, out)?;
+    }
+
+    out.extend_from_slice(&units[pos..]);
+    Ok(())
+}
+
+// Expands the reference starting at the `$` at `start`, and returns the index after it
+fn expand_ref(
+    units: &[CodeUnit],
+    start: usize,
+    base: usize,
+    lookup: &dyn Fn(&OsStr) -> Option<OsString>,
+    out: &mut Vec<CodeUnit>,
+) -> Result<usize, ExpandError> {
+    match units.get(start + 1) {
+        Some(&ch) if ch == unit(b'$') => {
+            out.push(ch);
+            Ok(start + 2)
+        }
+
+        Some(&ch) if ch == unit(b'{') => {
+            let name_start = start + 2;
+            let name_end = name_start + name_len(&units[name_start..]);
+            let close = find_close(units, name_end).ok_or(ExpandError::Unterminated {
+                position: base + start,
+            })?;
+
+            if name_end == name_start {
+                return Err(ExpandError::BadSubstitution {
+                    position: base + start,
+                });
+            }
+            let name = name_from_units(&units[name_start..name_end]);
+            let value = lookup(OsStr::new(&name)).filter(|value| !value.is_empty());
+
+            let op = &units[name_end..close];
+            if op.is_empty() {
+                if let Some(value) = value {
+                    out.extend_from_slice(&code_units(&value));
+                }
+            } else if op.starts_with(&[unit(b':'), unit(b'-')]) {
+                match value {
+                    Some(value) => out.extend_from_slice(&code_units(&value)),
+                    None => {
+                        let default = from_code_units(op[2..].into());
+                        expand_into(&default, base + name_end + 2, lookup, out)?;
+                    }
+                }
+            } else if op.starts_with(&[unit(b':'), unit(b'?')]) {
+                match value {
+                    Some(value) => out.extend_from_slice(&code_units(&value)),
+                    None => {
+                        return Err(ExpandError::Unset {
+                            name,
+                            message: from_code_units(op[2..].into()),
+                        })
+                    }
+                }
+            } else {
+                return Err(ExpandError::BadSubstitution {
+                    position: base + start,
+                });
+            }
+
+            Ok(close + 1)
+        }
+
+        Some(&ch) if is_name_start(ch) => {
+            let name_end = start + 1 + name_len(&units[start + 1..]);
+            let name = name_from_units(&units[start + 1..name_end]);
+            if let Some(value) = lookup(OsStr::new(&name)) {
+                out.extend_from_slice(&code_units(&value));
+            }
+            Ok(name_end)
+        }
+
+        // Not a reference; keep the "$"
+        _ => {
+            out.push(unit(b'$'));
+            Ok(start + 1)
+        }
+    }
+}
+
+#[inline]
+fn unit(ch: u8) -> CodeUnit {
+    CodeUnit::from(ch)
+}
+
+#[inline]
+fn is_name_start(ch: CodeUnit) -> bool {
+    matches!(unit_to_ascii(ch), Some(ch) if ch == b'_' || ch.is_ascii_alphabetic())
+}
+
+#[inline]
+fn is_name_char(ch: CodeUnit) -> bool {
+    matches!(unit_to_ascii(ch), Some(ch) if ch == b'_' || ch.is_ascii_alphanumeric())
+}
+
+fn name_len(units: &[CodeUnit]) -> usize {
+    match units.first() {
+        Some(&ch) if is_name_start(ch) => units.iter().take_while(|&&ch| is_name_char(ch)).count(),
+        _ => 0,
+    }
+}
+
+fn name_from_units(units: &[CodeUnit]) -> String {
+    // Names are always ASCII
+    units
+        .iter()
+        .map(|&ch| unit_to_ascii(ch).unwrap() as char)
+        .collect()
+}
+
+// Finds the "}" matching a "${" (allowing nested references in the default value)
+fn find_close(units: &[CodeUnit], start: usize) -> Option<usize> {
+    let mut depth = 0;
+
+    for (i, &ch) in units.iter().enumerate().skip(start) {
+        if ch == unit(b'{') {
+            depth += 1;
+        } else if ch == unit(b'}') {
+            if depth == 0 {
+                return Some(i);
+            }
+            depth -= 1;
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &OsStr) -> Option<OsString> {
+        match name.to_str().unwrap() {
+            "HOME" => Some("/home/user".into()),
+            "A" => Some("a".into()),
+            "B_2" => Some("b b".into()),
+            "EMPTY" => Some("".into()),
+            _ => None,
+        }
+    }
+
+    fn expand(s: &str) -> Result<OsString, ExpandError> {
+        expand_vars(OsStr::new(s), lookup)
+    }
+
+    #[test]
+    fn test_expand() {
+        assert_eq!(expand("").unwrap(), "");
+        assert_eq!(expand("abc").unwrap(), "abc");
+        assert_eq!(expand("$A").unwrap(), "a");
+        assert_eq!(expand("x$A.$B_2/").unwrap(), "xa.b b/");
+        assert_eq!(expand("${A}x").unwrap(), "ax");
+        assert_eq!(expand("$Ax").unwrap(), "");
+        assert_eq!(expand("$UNSET|${UNSET}").unwrap(), "|");
+
+        assert_eq!(expand("$$A").unwrap(), "$A");
+        assert_eq!(expand("$").unwrap(), "$");
+        assert_eq!(expand("a$ $1 $-").unwrap(), "a$ $1 $-");
+
+        assert_eq!(expand("${UNSET:-def}").unwrap(), "def");
+        assert_eq!(expand("${EMPTY:-def}").unwrap(), "def");
+        assert_eq!(expand("${A:-def}").unwrap(), "a");
+        assert_eq!(expand("${UNSET:-$A-${B_2}}").unwrap(), "a-b b");
+        assert_eq!(expand("${UNSET:-${EMPTY:-x}}y").unwrap(), "xy");
+
+        assert_eq!(expand("${A:?missing}").unwrap(), "a");
+        assert_eq!(
+            expand("${EMPTY:?missing}").unwrap_err(),
+            ExpandError::Unset {
+                name: "EMPTY".into(),
+                message: "missing".into(),
+            }
+        );
+        assert_eq!(
+            expand("${UNSET:?}").unwrap_err().to_string(),
+            "UNSET: parameter null or not set"
+        );
+    }
+
+    #[test]
+    fn test_tilde() {
+        assert_eq!(expand("~").unwrap(), "/home/user");
+        assert_eq!(expand("~/x").unwrap(), "/home/user/x");
+        assert_eq!(expand("~user/x").unwrap(), "~user/x");
+        assert_eq!(expand("a/~").unwrap(), "a/~");
+        assert_eq!(expand("~/$A").unwrap(), "/home/user/a");
+        assert_eq!(expand_vars(OsStr::new("~/x"), |_| None).unwrap(), "~/x");
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            expand("ab${A").unwrap_err(),
+            ExpandError::Unterminated { position: 2 }
+        );
+        assert_eq!(
+            expand("${").unwrap_err(),
+            ExpandError::Unterminated { position: 0 }
+        );
+        assert_eq!(
+            expand("ab${1").unwrap_err(),
+            ExpandError::Unterminated { position: 2 }
+        );
+        assert_eq!(
+            expand("${}").unwrap_err(),
+            ExpandError::BadSubstitution { position: 0 }
+        );
+        assert_eq!(
+            expand("x${1}").unwrap_err(),
+            ExpandError::BadSubstitution { position: 1 }
+        );
+        assert_eq!(
+            expand("${A%x}").unwrap_err(),
+            ExpandError::BadSubstitution { position: 0 }
+        );
+        assert_eq!(
+            expand("${UNSET:-${}}").unwrap_err(),
+            ExpandError::BadSubstitution { position: 9 }
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_invalid_unicode() {
+        use std::os::unix::ffi::OsStrExt;
+
+        assert_eq!(
+            expand_vars(OsStr::from_bytes(b"\xff$A\xfe${UNSET:-\x80}"), lookup).unwrap(),
+            OsStr::from_bytes(b"\xffa\xfe\x80")
+        );
+    }
+}
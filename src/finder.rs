@@ -0,0 +1,105 @@
+//! A reusable searcher for a fixed needle.
+
+use std::ffi::{OsStr, OsString};
+
+#[cfg(windows)]
+use crate::OsStrExt2;
+
+/// A needle that has been prepared for searching many haystacks.
+///
+/// On Unix with the `memchr` feature, this builds the searcher once up front; otherwise it just
+/// avoids re-encoding the needle for each search.
+#[derive(Clone, Debug)]
+pub struct OsStrFinder {
+    needle: OsString,
+    #[cfg(all(unix, feature = "memchr"))]
+    searcher: memchr::memmem::Finder<'static>,
+}
+
+impl OsStrFinder {
+    pub fn new(needle: &OsStr) -> Self {
+        Self {
+            needle: needle.into(),
+            #[cfg(all(unix, feature = "memchr"))]
+            searcher: memchr::memmem::Finder::new(crate::encoded_bytes(needle)).into_owned(),
+        }
+    }
+
+    #[inline]
+    pub fn needle(&self) -> &OsStr {
+        &self.needle
+    }
+
+    /// Find the first occurrence of the needle in `haystack`, in the same units as
+    /// [`OsStrExt2::find()`](crate::OsStrExt2::find).
+    #[inline]
+    pub fn find(&self, haystack: &OsStr) -> Option<usize> {
+        #[cfg(unix)]
+        return self.find_bytes(crate::encoded_bytes(haystack));
+
+        #[cfg(windows)]
+        return haystack.find(&self.needle);
+    }
+
+    #[inline]
+    pub fn is_match(&self, haystack: &OsStr) -> bool {
+        self.find(haystack).is_some()
+    }
+
+    /// Iterate over all the (possibly overlapping) occurrences of the needle in `haystack`, like
+    /// [`OsStrExt2::find_all()`](crate::OsStrExt2::find_all).
+    pub fn find_all<'a>(&'a self, haystack: &'a OsStr) -> impl Iterator<Item = usize> + 'a {
+        #[cfg(unix)]
+        {
+            let haystack = crate::encoded_bytes(haystack);
+            let mut pos = 0;
+
+            std::iter::from_fn(move || {
+                let index = pos + self.find_bytes(haystack.get(pos..)?)?;
+                pos = index + 1;
+                Some(index)
+            })
+        }
+
+        #[cfg(windows)]
+        haystack.find_all(&self.needle)
+    }
+
+    #[cfg(unix)]
+    #[inline]
+    fn find_bytes(&self, haystack: &[u8]) -> Option<usize> {
+        #[cfg(feature = "memchr")]
+        return self.searcher.find(haystack);
+
+        #[cfg(not(feature = "memchr"))]
+        return crate::find_bytes(haystack, crate::encoded_bytes(&self.needle));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::OsStrExt2;
+
+    #[test]
+    fn test_finder() {
+        let haystacks = ["", "a", "abc", "abcabc", "aaaa", "xyz"];
+
+        for needle in ["", "a", "abc", "aa", "z"].iter() {
+            let finder = OsStrFinder::new(OsStr::new(needle));
+            assert_eq!(finder.needle(), *needle);
+
+            for haystack in haystacks.iter() {
+                let haystack = OsStr::new(haystack);
+                let needle = OsStr::new(needle);
+
+                assert_eq!(finder.find(haystack), haystack.find(needle));
+                assert_eq!(finder.is_match(haystack), haystack.find(needle).is_some());
+                assert_eq!(
+                    finder.find_all(haystack).collect::<Vec<_>>(),
+                    haystack.find_all(needle).collect::<Vec<_>>()
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,286 @@
+//! Building `OsString`s with formatting macros.
+//!
+//! See [`os_format!`](crate::os_format), [`os_write!`](crate::os_write) and
+//! [`os_concat!`](crate::os_concat).
+
+use std::cell::RefCell;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+
+/// A builder for `OsString`s that accepts both formatted text and raw `OsStr`s.
+///
+/// Text can be written with the `fmt::Write` methods (including `write!`); use
+/// [`os_write!`](crate::os_write) to format `OsStr` and `Path` arguments without losing data.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct OsStringWriter {
+    buf: OsString,
+}
+
+impl OsStringWriter {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub fn with_capacity(cap: usize) -> Self {
+        Self {
+            buf: OsString::with_capacity(cap),
+        }
+    }
+
+    /// Append an `OsStr` exactly as is.
+    #[inline]
+    pub fn push_os(&mut self, s: &OsStr) {
+        self.buf.push(s);
+    }
+
+    #[inline]
+    pub fn push_str(&mut self, s: &str) {
+        self.buf.push(s);
+    }
+
+    #[inline]
+    pub fn as_os_str(&self) -> &OsStr {
+        &self.buf
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.buf.clear();
+    }
+
+    #[inline]
+    pub fn into_os_string(self) -> OsString {
+        self.buf
+    }
+}
+
+impl fmt::Write for OsStringWriter {
+    #[inline]
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push_str(s);
+        Ok(())
+    }
+}
+
+impl From<OsString> for OsStringWriter {
+    #[inline]
+    fn from(buf: OsString) -> Self {
+        Self { buf }
+    }
+}
+
+impl From<OsStringWriter> for OsString {
+    #[inline]
+    fn from(w: OsStringWriter) -> Self {
+        w.buf
+    }
+}
+
+/// Append formatted text to an [`OsStringWriter`].
+///
+/// This takes a format string and positional arguments like `write!`. Arguments that implement
+/// `AsRef<OsStr>` (like `OsStr`, `Path` and their owned versions) are inserted exactly, even if
+/// they aren't valid Unicode; everything else is formatted with `Display` (or `Debug`, for
+/// `{:?}`) as usual.
+///
+/// Format specs like width and precision are applied to `OsStr` arguments that are valid
+/// Unicode, and ignored for ones that aren't.
+#[macro_export]
+macro_rules! os_write {
+    ($w:expr, $fmt:literal $(, $arg:expr)* $(,)?) => {{
+        #[allow(unused_imports)]
+        use $crate::format::__private::{DisplayArg as _, OsStrArg as _};
+        let out = $crate::format::__private::Output::new(&mut $w);
+        out.write(format_args!(
+            $fmt $(, (&&$crate::format::__private::Arg(&$arg)).os_format_arg(&out))*
+        ));
+    }};
+}
+
+/// Like `format!`, but returns an `OsString` and inserts `OsStr` and `Path` arguments exactly.
+///
+/// See [`os_write!`](crate::os_write) for how arguments are handled.
+///
+/// ```
+/// # use std::ffi::OsStr;
+/// # use std::path::Path;
+/// let s = ffi_ext::os_format!("{}: {} ({:03})", Path::new("/tmp"), OsStr::new("x"), 7);
+/// assert_eq!(s, "/tmp: x (007)");
+/// ```
+#[macro_export]
+macro_rules! os_format {
+    ($($args:tt)*) => {{
+        let mut w = $crate::format::OsStringWriter::new();
+        $crate::os_write!(w, $($args)*);
+        w.into_os_string()
+    }};
+}
+
+/// Concatenate `OsStr` and `Display` arguments into an `OsString`.
+///
+/// `os_concat!(a, b)` is equivalent to `os_format!("{}{}", a, b)`.
+#[macro_export]
+macro_rules! os_concat {
+    ($($arg:expr),* $(,)?) => {{
+        #[allow(unused_mut)]
+        let mut w = $crate::format::OsStringWriter::new();
+        $($crate::os_write!(w, "{}", $arg);)*
+        w.into_os_string()
+    }};
+}
+
+// Implementation details of `os_write!`.
+//
+// `OsStr` arguments that aren't valid Unicode can't be passed through a `fmt::Formatter`, so they
+// are pushed directly onto the output instead. This is safe to interleave with the formatted text
+// because formatting happens strictly in order. Which path an argument takes is chosen with
+// autoref-based specialization on `Arg`.
+#[doc(hidden)]
+pub mod __private {
+    use super::*;
+
+    pub struct Output<'w>(RefCell<&'w mut OsStringWriter>);
+
+    impl<'w> Output<'w> {
+        #[inline]
+        pub fn new(w: &'w mut OsStringWriter) -> Self {
+            Self(RefCell::new(w))
+        }
+
+        pub fn write(&self, args: fmt::Arguments) {
+            fmt::Write::write_fmt(&mut Sink(self), args)
+                .expect("a formatting trait implementation returned an error");
+        }
+    }
+
+    struct Sink<'a, 'w>(&'a Output<'w>);
+
+    impl fmt::Write for Sink<'_, '_> {
+        #[inline]
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            self.0 .0.borrow_mut().push_str(s);
+            Ok(())
+        }
+    }
+
+    pub struct Arg<'a, T: ?Sized>(pub &'a T);
+
+    pub struct OsStrSlot<'s, 'w> {
+        out: &'s Output<'w>,
+        s: &'s OsStr,
+    }
+
+    impl fmt::Display for OsStrSlot<'_, '_> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self.s.to_str() {
+                Some(s) => fmt::Display::fmt(s, f),
+                None => {
+                    self.out.0.borrow_mut().push_os(self.s);
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    impl fmt::Debug for OsStrSlot<'_, '_> {
+        #[inline]
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            fmt::Debug::fmt(self.s, f)
+        }
+    }
+
+    pub trait OsStrArg {
+        fn os_format_arg<'s, 'w>(&'s self, out: &'s Output<'w>) -> OsStrSlot<'s, 'w>;
+    }
+
+    impl<T: AsRef<OsStr> + ?Sized> OsStrArg for &Arg<'_, T> {
+        #[inline]
+        fn os_format_arg<'s, 'w>(&'s self, out: &'s Output<'w>) -> OsStrSlot<'s, 'w> {
+            OsStrSlot {
+                out,
+                s: self.0.as_ref(),
+            }
+        }
+    }
+
+    pub trait DisplayArg {
+        type Target: ?Sized;
+
+        fn os_format_arg<'s>(&'s self, out: &Output) -> &'s Self::Target;
+    }
+
+    impl<T: ?Sized> DisplayArg for Arg<'_, T> {
+        type Target = T;
+
+        #[inline]
+        fn os_format_arg<'s>(&'s self, _out: &Output) -> &'s T {
+            self.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fmt::Write;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn test_os_format() {
+        assert_eq!(crate::os_format!(""), "");
+        assert_eq!(crate::os_format!("{{}}"), "{}");
+        assert_eq!(
+            crate::os_format!(
+                "{}|{}|{}|{}|{}",
+                OsStr::new("a"),
+                OsString::from("b"),
+                Path::new("c/d"),
+                PathBuf::from("e"),
+                1.5,
+            ),
+            "a|b|c/d|e|1.5"
+        );
+        assert_eq!(
+            crate::os_format!("{:>3}|{:?}|{1}|{:x}", "a", Path::new("b"), 255),
+            "  a|\"b\"|b|ff"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_os_format_invalid() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let s = OsStr::from_bytes(b"a\xffb");
+        assert_eq!(
+            crate::os_format!("<{}> {} <{:>5}>", s, 2, s),
+            OsStr::from_bytes(b"<a\xffb> 2 <a\xffb>")
+        );
+        assert_eq!(
+            crate::os_concat!("x", s, 3, Path::new("/")),
+            OsStr::from_bytes(b"xa\xffb3/")
+        );
+    }
+
+    #[test]
+    fn test_writer() {
+        let mut w = OsStringWriter::new();
+        assert!(w.is_empty());
+
+        write!(w, "{}-", 1).unwrap();
+        w.push_os(OsStr::new("x"));
+        crate::os_write!(w, "{}{}", Path::new("/y"), '!');
+        assert_eq!(w.as_os_str(), "1-x/y!");
+        assert_eq!(OsString::from(w), "1-x/y!");
+
+        assert_eq!(crate::os_concat!(), "");
+        assert_eq!(crate::os_concat!("a", OsStr::new("b"), 'c', 1,), "abc1");
+    }
+}
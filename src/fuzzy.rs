@@ -0,0 +1,299 @@
+//! Fuzzy subsequence matching (like fzf) for `OsStr`s.
+
+use std::ffi::OsStr;
+use std::path::is_separator;
+
+use crate::{char_unit_len, for_each_chunk, Chunk};
+
+const SCORE_MATCH: i64 = 16;
+const GAP_START: i64 = 3;
+const GAP_EXTENSION: i64 = 1;
+const BONUS_SEPARATOR: i64 = 9;
+const BONUS_BOUNDARY: i64 = 8;
+const BONUS_CAMEL: i64 = 7;
+const BONUS_CONSECUTIVE: i64 = 4;
+// The bonus for the first character of the pattern is multiplied by this
+const FIRST_CHAR_MULTIPLIER: i64 = 2;
+
+/// How [`fuzzy_match_with()`] compares letters.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum CaseMode {
+    Sensitive,
+    Insensitive,
+    /// Case-insensitive, unless the pattern contains an uppercase letter.
+    Smart,
+}
+
+/// A successful match from [`fuzzy_match()`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FuzzyMatch {
+    /// Higher is better. Scores are only meaningful relative to other matches for the same
+    /// pattern.
+    pub score: i64,
+    /// The offset of each matched character in the candidate, in the same units as
+    /// [`OsStrExt2::find()`](crate::OsStrExt2::find).
+    pub positions: Vec<usize>,
+}
+
+/// Check whether all the characters in `pattern` appear in `candidate` in order, and score the
+/// best way they can be matched. This uses [`CaseMode::Smart`].
+///
+/// Matches at the start of words (after path separators, punctuation or whitespace, and at
+/// camelCase transitions) and runs of consecutive matches score higher, and gaps score lower.
+/// Parts of `candidate` that aren't valid Unicode can never be matched.
+#[inline]
+pub fn fuzzy_match(pattern: &str, candidate: &OsStr) -> Option<FuzzyMatch> {
+    fuzzy_match_with(pattern, candidate, CaseMode::Smart)
+}
+
+/// Like [`fuzzy_match()`], with a custom [`CaseMode`].
+pub fn fuzzy_match_with(pattern: &str, candidate: &OsStr, mode: CaseMode) -> Option<FuzzyMatch> {
+    let ignore_case = match mode {
+        CaseMode::Sensitive => false,
+        CaseMode::Insensitive => true,
+        CaseMode::Smart => !pattern.chars().any(char::is_uppercase),
+    };
+    let fold = |ch: char| if ignore_case { fold_case(ch) } else { ch };
+
+    let pattern: Vec<char> = pattern.chars().map(fold).collect();
+    let symbols = symbols(candidate);
+
+    // Quick check that it matches at all
+    let mut pending = pattern.iter().peekable();
+    for sym in symbols.iter() {
+        if pending.peek().map(|&&p| sym.ch.map(fold) == Some(p)) == Some(true) {
+            pending.next();
+        }
+    }
+    if pending.peek().is_some() {
+        return None;
+    }
+
+    let n = symbols.len();
+    let mut prev_row: Vec<Option<i64>> = vec![None; n];
+    // The bonus at the start of the run of consecutive matches ending at each position
+    let mut prev_run_bonus = vec![0; n];
+    // For each pattern character and candidate position, the position the previous pattern
+    // character was matched at in the best match ending here
+    let mut back = vec![vec![0; n]; pattern.len()];
+
+    for (i, &p) in pattern.iter().enumerate() {
+        let mut row = vec![None; n];
+        let mut run_bonus = vec![0; n];
+        // The best score for the previous pattern character followed by a gap, and where it was
+        let mut carry: Option<(i64, usize)> = None;
+
+        for j in 0..n {
+            if j >= 2 {
+                // Extend the gap, or start one after the match at `j - 2`
+                carry = carry.map(|(score, k)| (score - GAP_EXTENSION, k));
+                if let Some(score) = prev_row[j - 2] {
+                    let started = score - GAP_START;
+                    if carry.map_or(true, |(best, _)| started > best) {
+                        carry = Some((started, j - 2));
+                    }
+                }
+            }
+
+            if symbols[j].ch.map(fold) != Some(p) {
+                continue;
+            }
+
+            let bonus = symbols[j].bonus;
+            run_bonus[j] = bonus;
+            if i == 0 {
+                row[j] = Some(SCORE_MATCH + bonus * FIRST_CHAR_MULTIPLIER);
+                continue;
+            }
+
+            // Consecutive matches get (at least) the bonus from the start of the run
+            let consecutive = match j {
+                0 => None,
+                _ => prev_row[j - 1].map(|score| {
+                    let bonus = bonus.max(prev_run_bonus[j - 1]).max(BONUS_CONSECUTIVE);
+                    (score + bonus, bonus)
+                }),
+            };
+            let gap = carry.map(|(score, k)| (score + bonus, k));
+
+            match (consecutive, gap) {
+                (Some((c, _)), Some((g, k))) if g > c => {
+                    row[j] = Some(g + SCORE_MATCH);
+                    back[i][j] = k;
+                }
+                (Some((c, run)), _) => {
+                    row[j] = Some(c + SCORE_MATCH);
+                    run_bonus[j] = run;
+                    back[i][j] = j - 1;
+                }
+                (None, Some((g, k))) => {
+                    row[j] = Some(g + SCORE_MATCH);
+                    back[i][j] = k;
+                }
+                (None, None) => (),
+            }
+        }
+
+        prev_row = row;
+        prev_run_bonus = run_bonus;
+    }
+
+    if pattern.is_empty() {
+        return Some(FuzzyMatch {
+            score: 0,
+            positions: Vec::new(),
+        });
+    }
+
+    // Pick the best end position (the earliest one if there's a tie), then walk back
+    let (mut j, score) = prev_row
+        .iter()
+        .enumerate()
+        .filter_map(|(j, score)| score.map(|score| (j, score)))
+        .fold(None, |best: Option<(usize, i64)>, (j, score)| match best {
+            Some((_, best_score)) if best_score >= score => best,
+            _ => Some((j, score)),
+        })?;
+
+    let mut positions = vec![0; pattern.len()];
+    for i in (0..pattern.len()).rev() {
+        positions[i] = symbols[j].offset;
+        j = back[i][j];
+    }
+
+    Some(FuzzyMatch { score, positions })
+}
+
+struct Symbol {
+    // `None` for invalid code units
+    ch: Option<char>,
+    offset: usize,
+    bonus: i64,
+}
+
+fn symbols(s: &OsStr) -> Vec<Symbol> {
+    let mut symbols = Vec::with_capacity(s.len());
+    let mut offset = 0;
+    let mut prev: Option<char> = None;
+
+    for_each_chunk(s, |chunk| match chunk {
+        Chunk::Valid(s) => {
+            for ch in s.chars() {
+                symbols.push(Symbol {
+                    ch: Some(ch),
+                    offset,
+                    bonus: bonus(prev, ch),
+                });
+                offset += char_unit_len(ch);
+                prev = Some(ch);
+            }
+        }
+
+        Chunk::Invalid(units) => {
+            for _ in units {
+                symbols.push(Symbol {
+                    ch: None,
+                    offset,
+                    bonus: 0,
+                });
+                offset += 1;
+            }
+            // Treat invalid units like letters, so the next character isn't a word start
+            prev = Some('a');
+        }
+    });
+
+    symbols
+}
+
+fn bonus(prev: Option<char>, ch: char) -> i64 {
+    let prev = match prev {
+        Some(prev) => prev,
+        None => return BONUS_BOUNDARY,
+    };
+
+    if !ch.is_alphanumeric() {
+        0
+    } else if is_separator(prev) {
+        BONUS_SEPARATOR
+    } else if !prev.is_alphanumeric() {
+        BONUS_BOUNDARY
+    } else if (prev.is_lowercase() && ch.is_uppercase()) || (!prev.is_numeric() && ch.is_numeric())
+    {
+        BONUS_CAMEL
+    } else {
+        0
+    }
+}
+
+fn fold_case(ch: char) -> char {
+    let mut lower = ch.to_lowercase();
+    match (lower.next(), lower.next()) {
+        (Some(l), None) => l,
+        _ => ch,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn positions(pattern: &str, candidate: &str) -> Option<Vec<usize>> {
+        fuzzy_match(pattern, OsStr::new(candidate)).map(|m| m.positions)
+    }
+
+    fn score(pattern: &str, candidate: &str) -> i64 {
+        fuzzy_match(pattern, OsStr::new(candidate)).unwrap().score
+    }
+
+    #[test]
+    fn test_match() {
+        assert_eq!(positions("", "abc"), Some(vec![]));
+        assert_eq!(positions("abc", "abc"), Some(vec![0, 1, 2]));
+        assert_eq!(positions("ac", "abc"), Some(vec![0, 2]));
+        assert_eq!(positions("abd", "abc"), None);
+        assert_eq!(positions("a", ""), None);
+        assert_eq!(positions("é", "café"), Some(vec![3]));
+
+        // Prefers word starts and consecutive runs
+        assert_eq!(positions("fb", "foo_bar"), Some(vec![0, 4]));
+        assert_eq!(positions("bar", "b_a_r_bar"), Some(vec![6, 7, 8]));
+        assert_eq!(positions("mf", "src/main/foo.rs"), Some(vec![4, 9]));
+        assert_eq!(positions("fb", "afooFooBar"), Some(vec![4, 7]));
+    }
+
+    #[test]
+    fn test_score() {
+        assert!(score("abc", "abc") > score("abc", "a_b_c"));
+        assert!(score("abc", "a_b_c") > score("abc", "axxbxxc"));
+        assert!(score("main", "src/main.rs") > score("main", "src/domain.rs"));
+        assert!(score("fb", "foo/bar") > score("fb", "fxxb"));
+        assert!(score("mc", "MyClass") > score("mc", "mxxc"));
+    }
+
+    #[test]
+    fn test_case() {
+        let s = OsStr::new("FooBar");
+
+        assert!(fuzzy_match("fb", s).is_some());
+        assert!(fuzzy_match("fB", s).is_none());
+        assert!(fuzzy_match("FB", s).is_some());
+
+        assert!(fuzzy_match_with("fb", s, CaseMode::Sensitive).is_none());
+        assert!(fuzzy_match_with("fB", s, CaseMode::Insensitive).is_some());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_invalid_unicode() {
+        use crate::OsStrExt2;
+        use std::os::unix::ffi::OsStrExt;
+
+        let s = OsStr::from_bytes(b"a\xff\xfeb/\xe9c");
+        let m = fuzzy_match("abc", s).unwrap();
+        assert_eq!(m.positions, [0, 3, 6]);
+        assert_eq!(s.substr(6, 7), "c");
+
+        assert!(fuzzy_match("\u{fffd}", s).is_none());
+    }
+}
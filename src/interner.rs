@@ -0,0 +1,386 @@
+//! Deduplicating `OsStr`s into compact [`Symbol`]s.
+
+use std::collections::hash_map::RandomState;
+use std::ffi::OsStr;
+use std::fmt;
+use std::hash::BuildHasher;
+use std::ops::Index;
+use std::sync::{RwLock, RwLockReadGuard};
+
+use crate::OsStrExt2;
+
+/// A handle to a string stored in an [`OsStrInterner`].
+///
+/// Symbols are numbered from 0 in the order that their strings were first interned.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    #[inline]
+    pub fn as_u32(self) -> u32 {
+        self.0
+    }
+}
+
+/// Maps strings to [`Symbol`]s and back.
+///
+/// The strings are stored back to back in a single buffer, and looking up a string that has
+/// already been interned doesn't allocate.
+#[derive(Clone, Default)]
+pub struct OsStrInterner {
+    bytes: Vec<u8>,
+    // Symbol `i` is `bytes[ends[i - 1]..ends[i]]` (starting at 0 for the first one)
+    ends: Vec<usize>,
+    hashes: Vec<u64>,
+    // An open-addressed hash table of symbols, plus 1 (0 means the slot is empty)
+    table: Vec<u32>,
+    hasher: RandomState,
+}
+
+impl OsStrInterner {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an interner with room for `strings` strings totalling `bytes` bytes.
+    pub fn with_capacity(strings: usize, bytes: usize) -> Self {
+        let mut interner = Self {
+            bytes: Vec::with_capacity(bytes),
+            ends: Vec::with_capacity(strings),
+            hashes: Vec::with_capacity(strings),
+            ..Self::default()
+        };
+        interner.resize_table(strings);
+        interner
+    }
+
+    /// Get the symbol for `s`, interning it if it hasn't been seen before.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `u32::MAX` strings have already been interned.
+    pub fn intern(&mut self, s: &OsStr) -> Symbol {
+        let bytes = crate::encoded_bytes(s);
+        let hash = self.hash(bytes);
+
+        if let Some(sym) = self.find(bytes, hash) {
+            return sym;
+        }
+
+        assert!(
+            self.ends.len() < u32::MAX as usize,
+            "too many strings interned"
+        );
+        self.resize_table(self.ends.len() + 1);
+
+        let sym = Symbol(self.ends.len() as u32);
+        self.bytes.extend_from_slice(bytes);
+        self.ends.push(self.bytes.len());
+        self.hashes.push(hash);
+
+        let slot = self.empty_slot(hash);
+        self.table[slot] = sym.0 + 1;
+        sym
+    }
+
+    /// Get the symbol for `s`, if it has been interned.
+    #[inline]
+    pub fn get(&self, s: &OsStr) -> Option<Symbol> {
+        let bytes = crate::encoded_bytes(s);
+        self.find(bytes, self.hash(bytes))
+    }
+
+    /// Get the string for `sym`, or `None` if it's out of range.
+    ///
+    /// Symbols aren't tied to the interner that created them, so a symbol from another interner
+    /// may resolve to an unrelated string.
+    #[inline]
+    pub fn resolve(&self, sym: Symbol) -> Option<&OsStr> {
+        let i = sym.0 as usize;
+        let end = *self.ends.get(i)?;
+        let start = if i == 0 { 0 } else { self.ends[i - 1] };
+
+        // These bytes were copied from `encoded_bytes()`
+        Some(unsafe { crate::from_encoded_bytes(&self.bytes[start..end]) })
+    }
+
+    /// The number of distinct strings that have been interned.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.ends.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.ends.is_empty()
+    }
+
+    /// Iterate over all the interned strings, in the order of their symbols.
+    pub fn iter(
+        &self,
+    ) -> impl DoubleEndedIterator<Item = (Symbol, &OsStr)> + ExactSizeIterator + '_ {
+        (0..self.ends.len() as u32).map(move |i| (Symbol(i), &self[Symbol(i)]))
+    }
+
+    /// Iterate over the interned strings that start with `prefix` (see
+    /// [`OsStrExt2::starts_with()`]).
+    #[inline]
+    pub fn with_prefix<'a>(
+        &'a self,
+        prefix: &'a OsStr,
+    ) -> impl Iterator<Item = (Symbol, &'a OsStr)> + 'a {
+        self.iter().filter(move |(_, s)| s.starts_with(prefix))
+    }
+
+    /// Iterate over the interned strings that end with `suffix` (see
+    /// [`OsStrExt2::ends_with()`]).
+    #[inline]
+    pub fn with_suffix<'a>(
+        &'a self,
+        suffix: &'a OsStr,
+    ) -> impl Iterator<Item = (Symbol, &'a OsStr)> + 'a {
+        self.iter().filter(move |(_, s)| s.ends_with(suffix))
+    }
+
+    fn hash(&self, bytes: &[u8]) -> u64 {
+        self.hasher.hash_one(bytes)
+    }
+
+    fn find(&self, bytes: &[u8], hash: u64) -> Option<Symbol> {
+        if self.table.is_empty() {
+            return None;
+        }
+
+        let mask = self.table.len() - 1;
+        let mut slot = hash as usize & mask;
+
+        loop {
+            let sym = match self.table[slot] {
+                0 => return None,
+                n => Symbol(n - 1),
+            };
+
+            if self.hashes[sym.0 as usize] == hash && crate::encoded_bytes(&self[sym]) == bytes {
+                return Some(sym);
+            }
+
+            slot = (slot + 1) & mask;
+        }
+    }
+
+    fn empty_slot(&self, hash: u64) -> usize {
+        let mask = self.table.len() - 1;
+        let mut slot = hash as usize & mask;
+
+        while self.table[slot] != 0 {
+            slot = (slot + 1) & mask;
+        }
+
+        slot
+    }
+
+    // Make sure the table can hold `count` symbols while staying at most half full
+    fn resize_table(&mut self, count: usize) {
+        let size = (count * 2).next_power_of_two().max(16);
+        if count == 0 || size <= self.table.len() {
+            return;
+        }
+
+        self.table = vec![0; size];
+        for i in 0..self.hashes.len() {
+            let slot = self.empty_slot(self.hashes[i]);
+            self.table[slot] = i as u32 + 1;
+        }
+    }
+}
+
+impl Index<Symbol> for OsStrInterner {
+    type Output = OsStr;
+
+    /// # Panics
+    ///
+    /// Panics if `sym` is out of range (see [`OsStrInterner::resolve()`]).
+    #[inline]
+    fn index(&self, sym: Symbol) -> &OsStr {
+        self.resolve(sym).expect("symbol out of range")
+    }
+}
+
+impl fmt::Debug for OsStrInterner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<'a> Extend<&'a OsStr> for OsStrInterner {
+    fn extend<I: IntoIterator<Item = &'a OsStr>>(&mut self, iter: I) {
+        for s in iter {
+            self.intern(s);
+        }
+    }
+}
+
+/// An [`OsStrInterner`] that can be shared between threads (for example, in an `Arc`).
+///
+/// Strings that have already been interned only need a read lock.
+#[derive(Debug, Default)]
+pub struct SyncOsStrInterner {
+    inner: RwLock<OsStrInterner>,
+}
+
+impl SyncOsStrInterner {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// See [`OsStrInterner::intern()`].
+    pub fn intern(&self, s: &OsStr) -> Symbol {
+        if let Some(sym) = self.get(s) {
+            return sym;
+        }
+
+        // Another thread may have interned it in between, but `intern()` handles that
+        self.inner.write().unwrap().intern(s)
+    }
+
+    /// See [`OsStrInterner::get()`].
+    #[inline]
+    pub fn get(&self, s: &OsStr) -> Option<Symbol> {
+        self.read().get(s)
+    }
+
+    /// Lock the interner for reading, to resolve symbols or iterate over it.
+    ///
+    /// Calling [`intern()`](Self::intern) with a new string while this is held will deadlock.
+    #[inline]
+    pub fn read(&self) -> RwLockReadGuard<'_, OsStrInterner> {
+        self.inner.read().unwrap()
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> OsStrInterner {
+        self.inner.into_inner().unwrap()
+    }
+}
+
+impl From<OsStrInterner> for SyncOsStrInterner {
+    #[inline]
+    fn from(interner: OsStrInterner) -> Self {
+        Self {
+            inner: RwLock::new(interner),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_intern() {
+        let mut interner = OsStrInterner::new();
+        assert!(interner.is_empty());
+        assert_eq!(interner.get(OsStr::new("a")), None);
+
+        let a = interner.intern(OsStr::new("a"));
+        let empty = interner.intern(OsStr::new(""));
+        let b = interner.intern(OsStr::new("b"));
+        assert_eq!((a.as_u32(), empty.as_u32(), b.as_u32()), (0, 1, 2));
+
+        assert_eq!(interner.intern(OsStr::new("a")), a);
+        assert_eq!(interner.intern(OsStr::new("")), empty);
+        assert_eq!(interner.get(OsStr::new("b")), Some(b));
+        assert_eq!(interner.len(), 3);
+
+        assert_eq!(&interner[a], "a");
+        assert_eq!(&interner[empty], "");
+        assert_eq!(interner.resolve(Symbol(3)), None);
+
+        // Enough to resize the table a few times
+        let names: Vec<String> = (0..1000).map(|i| format!("file{}.txt", i)).collect();
+        let syms: Vec<Symbol> = names
+            .iter()
+            .map(|name| interner.intern(OsStr::new(name)))
+            .collect();
+        for (name, &sym) in names.iter().zip(syms.iter()) {
+            assert_eq!(interner.get(OsStr::new(name)), Some(sym));
+            assert_eq!(&interner[sym], name.as_str());
+        }
+        assert_eq!(interner.len(), 1003);
+        assert_eq!(interner.get(OsStr::new("file1000.txt")), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let mut interner = OsStrInterner::with_capacity(2, 8);
+        let a = interner.intern(OsStr::from_bytes(b"caf\xe9"));
+        let b = interner.intern(OsStr::from_bytes(b"caf\xc3\xa9"));
+        assert_ne!(a, b);
+        assert_eq!(&interner[a], OsStr::from_bytes(b"caf\xe9"));
+        assert_eq!(&interner[b], "caf\u{e9}");
+    }
+
+    #[test]
+    fn test_iter() {
+        let mut interner = OsStrInterner::new();
+        interner.extend(
+            ["src", "lib.rs", "main.rs", "src", "README.md"]
+                .iter()
+                .map(OsStr::new),
+        );
+
+        let all: Vec<_> = interner.iter().map(|(_, s)| s).collect();
+        assert_eq!(all, ["src", "lib.rs", "main.rs", "README.md"]);
+        assert_eq!(interner.iter().len(), 4);
+        assert_eq!(interner.iter().next_back().unwrap().0.as_u32(), 3);
+
+        let rs: Vec<_> = interner
+            .with_suffix(OsStr::new(".rs"))
+            .map(|(sym, _)| sym.as_u32())
+            .collect();
+        assert_eq!(rs, [1, 2]);
+
+        let m: Vec<_> = interner
+            .with_prefix(OsStr::new("m"))
+            .map(|(_, s)| s)
+            .collect();
+        assert_eq!(m, ["main.rs"]);
+
+        assert_eq!(
+            format!("{:?}", interner).split(", ").next(),
+            Some("{Symbol(0): \"src\"")
+        );
+    }
+
+    #[test]
+    fn test_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<OsStrInterner>();
+        assert_send_sync::<SyncOsStrInterner>();
+
+        let interner = Arc::new(SyncOsStrInterner::new());
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let interner = interner.clone();
+                std::thread::spawn(move || {
+                    (0..100)
+                        .map(|i| interner.intern(OsStr::new(&i.to_string())))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        let results: Vec<_> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+        assert!(results.iter().all(|syms| *syms == results[0]));
+
+        let interner = Arc::try_unwrap(interner).unwrap().into_inner();
+        assert_eq!(interner.len(), 100);
+        assert_eq!(&interner[results[0][42]], "42");
+    }
+}
@@ -0,0 +1,197 @@
+//! Joining lists of `OsStr`s.
+
+use std::ffi::{OsStr, OsString};
+use std::iter::FusedIterator;
+
+/// Extension methods for slices of `OsStr`-like values.
+pub trait OsStrSliceExt {
+    type Item: AsRef<OsStr>;
+
+    /// Join the items together, with `sep` between each pair of items.
+    fn join_os(&self, sep: &OsStr) -> OsString;
+
+    /// Join the items together with no separator.
+    fn concat_os(&self) -> OsString;
+
+    /// Iterate over the items with `sep` between each pair of them.
+    ///
+    /// Collecting this into an `OsString` is equivalent to [`join_os()`](Self::join_os).
+    fn intersperse_os<'a>(&'a self, sep: &'a OsStr) -> IntersperseOs<'a, Self::Item>;
+}
+
+impl<T: AsRef<OsStr>> OsStrSliceExt for [T] {
+    type Item = T;
+
+    #[inline]
+    fn join_os(&self, sep: &OsStr) -> OsString {
+        let mut res = OsString::new();
+        res.extend_joined(self, sep);
+        res
+    }
+
+    #[inline]
+    fn concat_os(&self) -> OsString {
+        let mut res = OsString::new();
+        res.extend_from(self);
+        res
+    }
+
+    #[inline]
+    fn intersperse_os<'a>(&'a self, sep: &'a OsStr) -> IntersperseOs<'a, T> {
+        IntersperseOs {
+            items: self.iter(),
+            sep,
+            next_item: None,
+            started: false,
+        }
+    }
+}
+
+/// The iterator returned by [`OsStrSliceExt::intersperse_os()`].
+pub struct IntersperseOs<'a, T> {
+    items: std::slice::Iter<'a, T>,
+    sep: &'a OsStr,
+    // An item that was taken from `items` to check if a separator was needed
+    next_item: Option<&'a OsStr>,
+    started: bool,
+}
+
+impl<'a, T: AsRef<OsStr>> Iterator for IntersperseOs<'a, T> {
+    type Item = &'a OsStr;
+
+    fn next(&mut self) -> Option<&'a OsStr> {
+        if let Some(item) = self.next_item.take() {
+            return Some(item);
+        }
+
+        let item = self.items.next()?.as_ref();
+        if self.started {
+            self.next_item = Some(item);
+            Some(self.sep)
+        } else {
+            self.started = true;
+            Some(item)
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.len();
+        (len, Some(len))
+    }
+}
+
+impl<T: AsRef<OsStr>> ExactSizeIterator for IntersperseOs<'_, T> {
+    #[inline]
+    fn len(&self) -> usize {
+        let rest = self.items.len();
+        let pending = if self.next_item.is_some() { 1 } else { 0 };
+
+        if self.started {
+            // Each remaining item is preceded by a separator
+            rest * 2 + pending
+        } else {
+            (rest * 2).saturating_sub(1)
+        }
+    }
+}
+
+impl<T: AsRef<OsStr>> FusedIterator for IntersperseOs<'_, T> {}
+
+/// Extension methods for appending lists of `OsStr`s to an `OsString`.
+pub trait OsStringExt2 {
+    /// Append all the items, reserving the exact space needed first.
+    fn extend_from<T: AsRef<OsStr>>(&mut self, items: &[T]);
+
+    /// Append all the items with `sep` between each pair of them, reserving the exact space needed
+    /// first.
+    ///
+    /// No separator is added before the first item, even if the string isn't empty.
+    fn extend_joined<T: AsRef<OsStr>>(&mut self, items: &[T], sep: &OsStr);
+}
+
+impl OsStringExt2 for OsString {
+    fn extend_from<T: AsRef<OsStr>>(&mut self, items: &[T]) {
+        self.reserve_exact(items.iter().map(|item| item.as_ref().len()).sum());
+
+        for item in items {
+            self.push(item);
+        }
+    }
+
+    fn extend_joined<T: AsRef<OsStr>>(&mut self, items: &[T], sep: &OsStr) {
+        let items_len: usize = items.iter().map(|item| item.as_ref().len()).sum();
+        self.reserve_exact(items_len + sep.len() * items.len().saturating_sub(1));
+
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                self.push(sep);
+            }
+            self.push(item);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_join() {
+        let sep = OsStr::new(", ");
+
+        assert_eq!(([] as [&OsStr; 0]).join_os(sep), "");
+        assert_eq!(["a"].join_os(sep), "a");
+        assert_eq!(["a", "", "b"].join_os(sep), "a, , b");
+        assert_eq!(
+            [PathBuf::from("/a"), PathBuf::from("b")].join_os(OsStr::new(":")),
+            "/a:b"
+        );
+
+        assert_eq!(([] as [&OsStr; 0]).concat_os(), "");
+        assert_eq!(
+            [OsString::from("a"), OsString::from("bc")].concat_os(),
+            "abc"
+        );
+
+        let joined = ["abc", "d"].join_os(sep);
+        assert!(joined.capacity() >= joined.len());
+    }
+
+    #[test]
+    fn test_intersperse() {
+        let sep = OsStr::new("-");
+
+        for items in [&[][..], &["a"][..], &["a", "b", "c"][..]].iter() {
+            let iter = items.intersperse_os(sep);
+            let len = iter.len();
+            let parts: Vec<_> = iter.collect();
+            assert_eq!(parts.len(), len);
+            assert_eq!(parts.concat_os(), items.join_os(sep));
+        }
+
+        let mut iter = ["a", "b"].intersperse_os(sep);
+        assert_eq!(iter.len(), 3);
+        assert_eq!(iter.next(), Some(OsStr::new("a")));
+        assert_eq!(iter.len(), 2);
+        assert_eq!(iter.next(), Some(sep));
+        assert_eq!(iter.len(), 1);
+        assert_eq!(iter.next(), Some(OsStr::new("b")));
+        assert_eq!(iter.len(), 0);
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn test_extend() {
+        let mut s = OsString::from("x=");
+        s.extend_joined(&["a", "b"], OsStr::new(","));
+        assert_eq!(s, "x=a,b");
+
+        s.extend_from(&[OsStr::new("c"), OsStr::new("d")]);
+        assert_eq!(s, "x=a,bcd");
+
+        s.extend_joined::<&str>(&[], OsStr::new(","));
+        assert_eq!(s, "x=a,bcd");
+    }
+}
@@ -1,4 +1,4 @@
-use std::ffi::{OsStr, OsString};
+use std::ffi::{CStr, CString, NulError, OsStr, OsString};
 
 #[cfg(unix)]
 mod unix;
@@ -10,16 +10,176 @@ mod windows;
 #[cfg(windows)]
 pub use windows::*;
 
+pub mod args;
+// `newlocale()` and `strcoll_l()`/`strxfrm_l()` aren't available on every Unix
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "netbsd",
+    target_os = "openbsd",
+))]
+pub mod collate;
+pub mod common;
+pub mod distance;
+#[cfg(all(unix, feature = "encoding"))]
+pub mod encoding;
+#[cfg(all(not(unix), feature = "encoding"))]
+compile_error!("the `encoding` feature is only supported on Unix");
+pub mod env;
+pub mod expand;
+pub mod finder;
+pub mod format;
+pub mod fuzzy;
+pub mod interner;
+pub mod join;
+pub mod literal;
+#[cfg(unix)]
+pub mod nul_separated;
+#[cfg(feature = "rayon")]
+pub mod par;
+pub mod search;
+pub mod search_path;
+#[cfg(feature = "serde")]
+pub mod serde;
+pub mod small;
+#[cfg(unix)]
+pub mod stream;
+pub mod trie;
+pub mod wide;
+pub mod width;
+pub mod wtf8;
+
+pub(crate) use search::find_bytes;
+
+/// A run of either valid Unicode or invalid code units from an `OsStr`.
+pub(crate) enum Chunk<'a> {
+    Valid(&'a str),
+    Invalid(&'a [CodeUnit]),
+}
+
 pub trait OsStrExt2 {
     fn starts_with(&self, prefix: &OsStr) -> bool;
     fn ends_with(&self, suffix: &OsStr) -> bool;
 
-    fn find_all<'a>(&'a self, needle: &'a OsStr) -> OsStrFindIter;
+    fn find_all<'a>(&'a self, needle: &'a OsStr) -> OsStrFindIter<'a>;
 
     fn rfind(&self, needle: &OsStr) -> Option<usize>;
     fn find(&self, needle: &OsStr) -> Option<usize>;
 
     fn substr(&self, start: usize, end: usize) -> OsString;
+
+    /// Split this string on each (non-overlapping) occurrence of `sep`.
+    ///
+    /// As with `str::split()`, an empty separator matches at the start, the end, and between
+    /// every character.
+    ///
+    /// On Windows, this (and [`split_once()`](Self::split_once),
+    /// [`strip_prefix()`](Self::strip_prefix) and [`strip_suffix()`](Self::strip_suffix)) only
+    /// matches whole code points, because the pieces are borrowed from the original string. So
+    /// unlike [`find()`](Self::find), it never matches an unpaired surrogate in `sep` against half
+    /// of a surrogate pair: `"a\u{D800}"` is found in `"a\u{10000}"`, but doesn't split it.
+    fn split<'a>(&'a self, sep: &'a OsStr) -> OsStrSplit<'a>;
+    fn split_once(&self, sep: &OsStr) -> Option<(&OsStr, &OsStr)>;
+
+    fn strip_prefix(&self, prefix: &OsStr) -> Option<&OsStr>;
+    fn strip_suffix(&self, suffix: &OsStr) -> Option<&OsStr>;
+
+    /// Convert this string to a `CString` (using its WTF-8 representation on Windows), handling
+    /// interior NULs as specified by `policy`.
+    ///
+    /// This only fails if `policy` is [`NulPolicy::Error`].
+    fn to_cstring(&self, policy: NulPolicy) -> Result<CString, NulError>;
+}
+
+/// How to handle interior NULs when converting to a `CString`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum NulPolicy {
+    /// Fail with a `NulError`, which reports the position of the first NUL.
+    Error,
+    /// Cut the string off at the first NUL.
+    Truncate,
+    /// Replace each NUL with `\0`.
+    ///
+    /// To keep the result unambiguous, backslashes are also doubled (even if there are no NULs).
+    Escape,
+}
+
+pub(crate) fn bytes_to_cstring(bytes: Vec<u8>, policy: NulPolicy) -> Result<CString, NulError> {
+    match policy {
+        NulPolicy::Error => CString::new(bytes),
+
+        NulPolicy::Truncate => CString::new(bytes).or_else(|e| {
+            let nul_pos = e.nul_position();
+            let mut bytes = e.into_vec();
+            bytes.truncate(nul_pos);
+            // The first NUL is gone, so there are no NULs left
+            Ok(unsafe { CString::from_vec_unchecked(bytes) })
+        }),
+
+        NulPolicy::Escape => {
+            let mut escaped = Vec::with_capacity(bytes.len());
+            for ch in bytes {
+                match ch {
+                    b'\\' => escaped.extend_from_slice(b"\\\\"),
+                    b'\0' => escaped.extend_from_slice(b"\\0"),
+                    ch => escaped.push(ch),
+                }
+            }
+            // All the NULs have been escaped
+            Ok(unsafe { CString::from_vec_unchecked(escaped) })
+        }
+    }
+}
+
+/// Convert `s` to a C string and pass it to `f`, without allocating if `s` is short.
+///
+/// Fails if `s` contains interior NULs.
+pub fn with_c_str<T, F: FnOnce(&CStr) -> T>(s: &OsStr, f: F) -> Result<T, NulError> {
+    const MAX_STACK_LEN: usize = 384;
+
+    let bytes = to_wtf8(s);
+
+    if bytes.len() < MAX_STACK_LEN {
+        let mut buf = [0; MAX_STACK_LEN];
+        buf[..bytes.len()].copy_from_slice(&bytes);
+
+        // If this fails, there are interior NULs; fall through so we get the correct error
+        if let Ok(c_str) = CStr::from_bytes_with_nul(&buf[..bytes.len() + 1]) {
+            return Ok(f(c_str));
+        }
+    }
+
+    CString::new(bytes.into_owned()).map(|c_str| f(&c_str))
+}
+
+/// The byte-based search API of [`OsStrExt2`], for NUL-terminated strings.
+///
+/// Anything that ends at the end of the string is returned as a `&CStr`; anything else is returned
+/// as a byte slice (without a trailing NUL).
+#[cfg(unix)]
+pub trait CStrExt2 {
+    fn starts_with(&self, prefix: &CStr) -> bool;
+    fn ends_with(&self, suffix: &CStr) -> bool;
+
+    fn find_all<'a>(&'a self, needle: &'a CStr) -> OsStrFindIter<'a>;
+
+    fn rfind(&self, needle: &CStr) -> Option<usize>;
+    fn find(&self, needle: &CStr) -> Option<usize>;
+
+    fn split<'a>(&'a self, sep: &'a CStr) -> CStrSplit<'a>;
+    fn split_once(&self, sep: &CStr) -> Option<(&[u8], &CStr)>;
+    fn rsplit_once(&self, sep: &CStr) -> Option<(&[u8], &CStr)>;
+
+    fn strip_prefix(&self, prefix: &CStr) -> Option<&CStr>;
+    fn strip_suffix(&self, suffix: &CStr) -> Option<&[u8]>;
+
+    fn substr_from(&self, start: usize) -> &CStr;
+
+    fn to_os_str(&self) -> &OsStr;
 }
 
 #[cfg(test)]
@@ -137,6 +297,77 @@ mod tests {
         assert_eq!(OsStr::new("abc").substr(3, 3), OsStr::new(""));
     }
 
+    #[test]
+    fn test_split() {
+        fn split<'a>(s: &'a str, sep: &'a str) -> Vec<&'a OsStr> {
+            OsStr::new(s).split(OsStr::new(sep)).collect()
+        }
+
+        assert_eq!(split("a:b::c", ":"), ["a", "b", "", "c"]);
+        assert_eq!(split(":a:", ":"), ["", "a", ""]);
+        assert_eq!(split("a::b", "::"), ["a", "b"]);
+        assert_eq!(split("a:::b", "::"), ["a", ":b"]);
+        assert_eq!(split("abc", ":"), ["abc"]);
+        assert_eq!(split("", ":"), [""]);
+
+        assert_eq!(split("ab", ""), ["", "a", "b", ""]);
+        assert_eq!(split("", ""), ["", ""]);
+    }
+
+    #[test]
+    fn test_split_once() {
+        let s = OsStr::new("a=b=c");
+        assert_eq!(
+            s.split_once(OsStr::new("=")),
+            Some((OsStr::new("a"), OsStr::new("b=c")))
+        );
+        assert_eq!(
+            s.split_once(OsStr::new("b=")),
+            Some((OsStr::new("a="), OsStr::new("c")))
+        );
+        assert_eq!(
+            s.split_once(OsStr::new("")),
+            Some((OsStr::new(""), OsStr::new("a=b=c")))
+        );
+        assert_eq!(s.split_once(OsStr::new(":")), None);
+    }
+
+    #[test]
+    fn test_strip() {
+        let s = OsStr::new("--abc");
+        assert_eq!(s.strip_prefix(OsStr::new("--")), Some(OsStr::new("abc")));
+        assert_eq!(s.strip_prefix(OsStr::new("")), Some(s));
+        assert_eq!(s.strip_prefix(OsStr::new("-a")), None);
+        assert_eq!(s.strip_suffix(OsStr::new("bc")), Some(OsStr::new("--a")));
+        assert_eq!(s.strip_suffix(OsStr::new("--abc")), Some(OsStr::new("")));
+        assert_eq!(s.strip_suffix(OsStr::new("b")), None);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_split_surrogates() {
+        use std::os::windows::ffi::OsStringExt;
+
+        // U+10000 is the surrogate pair D800 DC00
+        let s = OsString::from_wide(&[0x61, 0xD800, 0xDC00, 0x62]);
+        let sep = OsString::from_wide(&[0x61, 0xD800]);
+        let tail = OsString::from_wide(&[0xDC00, 0x62]);
+
+        // find() works on code units, but these only match whole code points
+        assert_eq!(s.find(&sep), Some(0));
+        assert!(s.ends_with(&tail));
+        assert_eq!(s.split(&sep).collect::<Vec<_>>(), [s.as_os_str()]);
+        assert_eq!(s.split_once(&sep), None);
+        assert_eq!(s.strip_prefix(&sep), None);
+        assert_eq!(s.strip_suffix(&tail), None);
+
+        // Unpaired surrogates still match each other
+        let s = OsString::from_wide(&[0x61, 0xD800, 0x62]);
+        assert_eq!(s.split(&sep).collect::<Vec<_>>(), ["", "b"]);
+        assert_eq!(s.split_once(&sep), Some((OsStr::new(""), OsStr::new("b"))));
+        assert_eq!(s.strip_prefix(&sep), Some(OsStr::new("b")));
+    }
+
     fn collect_fused<T, I: Iterator<Item = T>>(mut it: I) -> Vec<T> {
         let res = it.by_ref().collect();
         // Check that it ends after None
@@ -157,10 +388,16 @@ mod tests {
 
         assert_eq!(
             collect_fused(OsStr::new("").find_all(OsStr::new("abc"))),
-            []
+            [] as [usize; 0]
+        );
+        assert_eq!(
+            collect_fused(OsStr::new("").find_all(OsStr::new("ab"))),
+            [] as [usize; 0]
+        );
+        assert_eq!(
+            collect_fused(OsStr::new("").find_all(OsStr::new("a"))),
+            [] as [usize; 0]
         );
-        assert_eq!(collect_fused(OsStr::new("").find_all(OsStr::new("ab"))), []);
-        assert_eq!(collect_fused(OsStr::new("").find_all(OsStr::new("a"))), []);
         assert_eq!(collect_fused(OsStr::new("").find_all(OsStr::new(""))), [0]);
 
         assert_eq!(
@@ -173,7 +410,7 @@ mod tests {
         );
         assert_eq!(
             collect_fused(OsStr::new("a").find_all(OsStr::new("ab"))),
-            []
+            [] as [usize; 0]
         );
 
         assert_eq!(
@@ -208,15 +445,15 @@ mod tests {
 
         assert_eq!(
             collect_fused(OsStr::new("").find_all(OsStr::new("abc")).rev()),
-            []
+            [] as [usize; 0]
         );
         assert_eq!(
             collect_fused(OsStr::new("").find_all(OsStr::new("ab")).rev()),
-            []
+            [] as [usize; 0]
         );
         assert_eq!(
             collect_fused(OsStr::new("").find_all(OsStr::new("a")).rev()),
-            []
+            [] as [usize; 0]
         );
         assert_eq!(
             collect_fused(OsStr::new("").find_all(OsStr::new("")).rev()),
@@ -233,7 +470,7 @@ mod tests {
         );
         assert_eq!(
             collect_fused(OsStr::new("a").find_all(OsStr::new("ab")).rev()),
-            []
+            [] as [usize; 0]
         );
 
         assert_eq!(
@@ -265,4 +502,127 @@ mod tests {
         assert_eq!(it.next_back(), None);
         assert_eq!(it.next(), None);
     }
+
+    #[cfg(unix)]
+    fn cstr(s: &str) -> std::ffi::CString {
+        std::ffi::CString::new(s).unwrap()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_cstr_search() {
+        let s = cstr("abcabc");
+
+        assert!(CStrExt2::starts_with(s.as_c_str(), &cstr("abc")));
+        assert!(!CStrExt2::starts_with(s.as_c_str(), &cstr("bc")));
+        assert!(CStrExt2::ends_with(s.as_c_str(), &cstr("bc")));
+        assert!(!CStrExt2::ends_with(s.as_c_str(), &cstr("ab")));
+
+        assert_eq!(CStrExt2::find(s.as_c_str(), &cstr("bc")), Some(1));
+        assert_eq!(CStrExt2::rfind(s.as_c_str(), &cstr("bc")), Some(4));
+        assert_eq!(CStrExt2::find(s.as_c_str(), &cstr("d")), None);
+        assert_eq!(CStrExt2::rfind(s.as_c_str(), &cstr("")), Some(6));
+
+        assert_eq!(
+            collect_fused(CStrExt2::find_all(s.as_c_str(), &cstr("c"))),
+            [2, 5]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_cstr_split_strip() {
+        let s = cstr("a,b,,c");
+
+        assert_eq!(
+            s.split(&cstr(",")).collect::<Vec<_>>(),
+            [&b"a"[..], b"b", b"", b"c"]
+        );
+        assert_eq!(
+            s.split(&cstr(",,")).collect::<Vec<_>>(),
+            [&b"a,b"[..], b"c"]
+        );
+        assert_eq!(cstr("").split(&cstr(",")).collect::<Vec<_>>(), [b""]);
+        assert_eq!(
+            cstr("ab").split(&cstr("")).collect::<Vec<_>>(),
+            [&b""[..], b"a", b"b", b""]
+        );
+
+        assert_eq!(
+            s.split_once(&cstr(",")),
+            Some((&b"a"[..], cstr("b,,c").as_c_str()))
+        );
+        assert_eq!(
+            s.rsplit_once(&cstr(",")),
+            Some((&b"a,b,"[..], cstr("c").as_c_str()))
+        );
+        assert_eq!(s.split_once(&cstr(";")), None);
+
+        assert_eq!(s.strip_prefix(&cstr("a,")), Some(cstr("b,,c").as_c_str()));
+        assert_eq!(s.strip_prefix(&cstr("b")), None);
+        assert_eq!(s.strip_suffix(&cstr(",c")), Some(&b"a,b,"[..]));
+        assert_eq!(s.strip_suffix(&cstr("b")), None);
+
+        assert_eq!(s.substr_from(0), s.as_c_str());
+        assert_eq!(s.substr_from(6), cstr("").as_c_str());
+    }
+
+    #[test]
+    fn test_to_cstring() {
+        let s = OsStr::new("a\\b\0c\0");
+
+        let err = s.to_cstring(NulPolicy::Error).unwrap_err();
+        assert_eq!(err.nul_position(), 3);
+        assert_eq!(
+            s.to_cstring(NulPolicy::Truncate).unwrap().as_bytes(),
+            b"a\\b"
+        );
+        assert_eq!(
+            s.to_cstring(NulPolicy::Escape).unwrap().as_bytes(),
+            b"a\\\\b\\0c\\0"
+        );
+
+        let s = OsStr::new("abc");
+        for &policy in [NulPolicy::Error, NulPolicy::Truncate, NulPolicy::Escape].iter() {
+            assert_eq!(s.to_cstring(policy).unwrap().as_bytes(), b"abc");
+        }
+    }
+
+    #[test]
+    fn test_with_c_str() {
+        assert_eq!(
+            with_c_str(OsStr::new("abc"), |s| s.to_bytes().len()).unwrap(),
+            3
+        );
+        assert_eq!(
+            with_c_str(OsStr::new(""), |s| s.to_bytes().len()).unwrap(),
+            0
+        );
+
+        let long = "a".repeat(1000);
+        assert_eq!(
+            with_c_str(OsStr::new(&long), |s| s.to_bytes().len()).unwrap(),
+            1000
+        );
+
+        assert_eq!(
+            with_c_str(OsStr::new("ab\0c"), |_| ())
+                .unwrap_err()
+                .nul_position(),
+            2
+        );
+        assert_eq!(
+            with_c_str(OsStr::new(&(long + "\0")), |_| ())
+                .unwrap_err()
+                .nul_position(),
+            1000
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_cstr_to_os_str() {
+        assert_eq!(cstr("abc").to_os_str(), OsStr::new("abc"));
+        assert_eq!(cstr("").to_os_str(), OsStr::new(""));
+    }
 }
@@ -0,0 +1,195 @@
+//! `OsStr` literals.
+//!
+//! `OsStr` has no `const` constructors on stable Rust, so `const` and `static` items can't hold
+//! a `&OsStr` directly. [`StaticOsStr`] can be built in a `const` context instead, and converted
+//! (for free) when it's used.
+
+use std::ffi::OsStr;
+use std::fmt;
+use std::ops::Deref;
+use std::path::Path;
+
+/// Create a `&'static OsStr` from a string literal, or (on Unix) a byte string literal.
+///
+/// ```
+/// use ffi_ext::{os_str, OsStrExt2};
+///
+/// assert_eq!(os_str!("a/b").find(os_str!("/")), Some(1));
+///
+/// #[cfg(unix)]
+/// {
+///     use std::os::unix::ffi::OsStrExt;
+///     assert_eq!(os_str!(b"caf\xe9").as_bytes(), b"caf\xe9");
+/// }
+/// ```
+///
+/// This can't be used to initialize `const` or `static` items; see [`StaticOsStr`] for that.
+#[macro_export]
+macro_rules! os_str {
+    ($lit:literal) => {
+        $crate::literal::__private::Literal::to_os_str($lit)
+    };
+}
+
+/// An `OsStr` that can be created in a `const` context, for tables of needles and the like.
+///
+/// This derefs to `&'static OsStr`.
+///
+/// ```
+/// use ffi_ext::literal::StaticOsStr;
+/// use ffi_ext::OsStrExt2;
+/// use std::ffi::OsStr;
+///
+/// static EXTENSIONS: &[StaticOsStr] = &[StaticOsStr::new(".tar.gz"), StaticOsStr::new(".tgz")];
+///
+/// let name = OsStr::new("archive.tgz");
+/// assert!(EXTENSIONS.iter().any(|ext| name.ends_with(ext)));
+/// ```
+#[derive(Clone, Copy)]
+pub struct StaticOsStr {
+    #[cfg(unix)]
+    bytes: &'static [u8],
+    #[cfg(windows)]
+    s: &'static str,
+}
+
+impl StaticOsStr {
+    #[inline]
+    pub const fn new(s: &'static str) -> Self {
+        #[cfg(unix)]
+        return Self {
+            bytes: s.as_bytes(),
+        };
+
+        #[cfg(windows)]
+        return Self { s };
+    }
+
+    /// Create a `StaticOsStr` from arbitrary bytes (which don't have to be valid UTF-8).
+    #[cfg(unix)]
+    #[inline]
+    pub const fn from_bytes(bytes: &'static [u8]) -> Self {
+        Self { bytes }
+    }
+
+    #[inline]
+    pub fn as_os_str(self) -> &'static OsStr {
+        #[cfg(unix)]
+        return std::os::unix::ffi::OsStrExt::from_bytes(self.bytes);
+
+        #[cfg(windows)]
+        return OsStr::new(self.s);
+    }
+}
+
+impl Deref for StaticOsStr {
+    type Target = OsStr;
+
+    #[inline]
+    fn deref(&self) -> &OsStr {
+        self.as_os_str()
+    }
+}
+
+impl AsRef<OsStr> for StaticOsStr {
+    #[inline]
+    fn as_ref(&self) -> &OsStr {
+        self.as_os_str()
+    }
+}
+
+impl AsRef<Path> for StaticOsStr {
+    #[inline]
+    fn as_ref(&self) -> &Path {
+        Path::new(self.as_os_str())
+    }
+}
+
+impl From<StaticOsStr> for &'static OsStr {
+    #[inline]
+    fn from(s: StaticOsStr) -> Self {
+        s.as_os_str()
+    }
+}
+
+impl PartialEq<OsStr> for StaticOsStr {
+    #[inline]
+    fn eq(&self, other: &OsStr) -> bool {
+        self.as_os_str() == other
+    }
+}
+
+impl fmt::Debug for StaticOsStr {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_os_str(), f)
+    }
+}
+
+// Implementation details of `os_str!`. Which literals are accepted is decided by which types
+// implement `Literal`, so byte strings fail to compile on Windows.
+#[doc(hidden)]
+pub mod __private {
+    use super::*;
+
+    pub trait Literal {
+        fn to_os_str(self) -> &'static OsStr;
+    }
+
+    impl Literal for &'static str {
+        #[inline]
+        fn to_os_str(self) -> &'static OsStr {
+            OsStr::new(self)
+        }
+    }
+
+    #[cfg(unix)]
+    impl<const N: usize> Literal for &'static [u8; N] {
+        #[inline]
+        fn to_os_str(self) -> &'static OsStr {
+            std::os::unix::ffi::OsStrExt::from_bytes(self)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::OsStrExt2;
+
+    const EMPTY: StaticOsStr = StaticOsStr::new("");
+    static NEEDLES: [StaticOsStr; 2] = [StaticOsStr::new("/"), StaticOsStr::new("\u{e9}")];
+
+    #[test]
+    fn test_os_str() {
+        let s: &'static OsStr = os_str!("caf\u{e9}/x");
+        assert_eq!(s, OsStr::new("caf\u{e9}/x"));
+        assert_eq!(os_str!(""), OsStr::new(""));
+        assert_eq!(os_str!(r"a\b"), OsStr::new("a\\b"));
+    }
+
+    #[test]
+    fn test_static() {
+        let s = OsStr::new("caf\u{e9}/x");
+        assert_eq!(s.find(&NEEDLES[0]), Some(5));
+        assert_eq!(s.find(&NEEDLES[1]), Some(3));
+        assert!(s.starts_with(&EMPTY));
+
+        assert_eq!(NEEDLES[0], *OsStr::new("/"));
+        assert_eq!(<&OsStr>::from(NEEDLES[1]), "\u{e9}");
+        assert_eq!(format!("{:?}", NEEDLES[1]), "\"\u{e9}\"");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_bytes() {
+        use std::os::unix::ffi::OsStrExt;
+
+        const LATIN1: StaticOsStr = StaticOsStr::from_bytes(b"\xe9");
+
+        let s = os_str!(b"caf\xe9");
+        assert_eq!(s.as_bytes(), b"caf\xe9");
+        assert_eq!(s.find(&LATIN1), Some(3));
+        assert_eq!(os_str!(b""), OsStr::new(""));
+    }
+}
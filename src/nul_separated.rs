@@ -0,0 +1,314 @@
+//! Parsing and building NUL-separated lists of strings.
+//!
+//! This is the format of `/proc/<pid>/cmdline`, `/proc/<pid>/environ`, and the output of
+//! `find -print0`. Each entry is followed by a NUL; a missing NUL after the last entry is
+//! tolerated.
+
+use std::ffi::{CStr, CString, NulError, OsStr, OsString};
+use std::io::{self, BufRead, Write};
+use std::iter::FusedIterator;
+use std::os::raw::c_char;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::ptr;
+
+use crate::SplitBytes;
+
+/// An iterator over the entries in a NUL-separated buffer.
+pub struct NulSeparated<'a> {
+    inner: SplitBytes<'a>,
+}
+
+impl<'a> NulSeparated<'a> {
+    #[inline]
+    pub fn new(buf: &'a [u8]) -> Self {
+        let empty = buf.is_empty();
+        // Drop the final terminator so it doesn't produce an extra empty entry at the end
+        let buf = buf.strip_suffix(b"\0").unwrap_or(buf);
+
+        let mut inner = SplitBytes::new(buf, b"\0");
+        if empty {
+            // An empty buffer has no entries, so consume the single empty piece
+            inner.next();
+        }
+
+        Self { inner }
+    }
+}
+
+impl<'a> Iterator for NulSeparated<'a> {
+    type Item = &'a OsStr;
+
+    #[inline]
+    fn next(&mut self) -> Option<&'a OsStr> {
+        self.inner.next().map(OsStr::from_bytes)
+    }
+}
+
+impl FusedIterator for NulSeparated<'_> {}
+
+/// An iterator that reads NUL-separated entries from a `BufRead`.
+pub struct NulSeparatedReader<R> {
+    reader: R,
+    done: bool,
+}
+
+impl<R: BufRead> NulSeparatedReader<R> {
+    #[inline]
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            done: false,
+        }
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: BufRead> Iterator for NulSeparatedReader<R> {
+    type Item = io::Result<OsString>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let mut buf = Vec::new();
+        match self.reader.read_until(b'\0', &mut buf) {
+            Ok(0) => {
+                self.done = true;
+                None
+            }
+
+            Ok(_) => {
+                if buf.last() == Some(&b'\0') {
+                    buf.pop();
+                }
+                Some(Ok(OsString::from_vec(buf)))
+            }
+
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl<R: BufRead> FusedIterator for NulSeparatedReader<R> {}
+
+/// Writes NUL-terminated entries to a `Write`.
+pub struct NulSeparatedWriter<W> {
+    writer: W,
+}
+
+impl<W: Write> NulSeparatedWriter<W> {
+    #[inline]
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    /// Write a single entry, followed by a NUL.
+    ///
+    /// This fails with `InvalidInput` if the entry contains a NUL (which would make the output
+    /// ambiguous).
+    pub fn write_entry(&mut self, entry: &OsStr) -> io::Result<()> {
+        let bytes = entry.as_bytes();
+        if crate::find_bytes(bytes, b"\0").is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "entry contains a NUL byte",
+            ));
+        }
+
+        self.writer.write_all(bytes)?;
+        self.writer.write_all(b"\0")
+    }
+
+    #[inline]
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// An owned list of C strings that can be passed as an `argv`/`envp`-style array.
+#[derive(Debug)]
+pub struct CStringArray {
+    strings: Vec<CString>,
+    // Always NULL-terminated. These point into the heap buffers of the `CString`s, so they stay
+    // valid even if `strings` is reallocated.
+    ptrs: Vec<*const c_char>,
+}
+
+// The pointers only refer to data we own
+unsafe impl Send for CStringArray {}
+unsafe impl Sync for CStringArray {}
+
+impl CStringArray {
+    #[inline]
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    pub fn with_capacity(cap: usize) -> Self {
+        let mut ptrs = Vec::with_capacity(cap + 1);
+        ptrs.push(ptr::null());
+
+        Self {
+            strings: Vec::with_capacity(cap),
+            ptrs,
+        }
+    }
+
+    /// Add a string to the end of the array; fails if it contains a NUL.
+    #[inline]
+    pub fn push(&mut self, s: &OsStr) -> Result<(), NulError> {
+        self.push_cstring(CString::new(s.as_bytes())?);
+        Ok(())
+    }
+
+    pub fn push_cstring(&mut self, s: CString) {
+        // Overwrite the NULL terminator, then add a new one
+        *self.ptrs.last_mut().unwrap() = s.as_ptr();
+        self.ptrs.push(ptr::null());
+        self.strings.push(s);
+    }
+
+    /// Get a pointer to the NULL-terminated array of string pointers.
+    ///
+    /// The pointer is valid until the array is modified or dropped.
+    #[inline]
+    pub fn as_ptr(&self) -> *const *const c_char {
+        self.ptrs.as_ptr()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = &CStr> + '_ {
+        self.strings.iter().map(CString::as_c_str)
+    }
+}
+
+impl Default for CStringArray {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Extend<CString> for CStringArray {
+    fn extend<I: IntoIterator<Item = CString>>(&mut self, iter: I) {
+        for s in iter {
+            self.push_cstring(s);
+        }
+    }
+}
+
+impl std::iter::FromIterator<CString> for CStringArray {
+    fn from_iter<I: IntoIterator<Item = CString>>(iter: I) -> Self {
+        let mut arr = Self::new();
+        arr.extend(iter);
+        arr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(buf: &[u8]) -> Vec<&OsStr> {
+        NulSeparated::new(buf).collect()
+    }
+
+    #[test]
+    fn test_nul_separated() {
+        assert_eq!(parse(b""), [] as [&OsStr; 0]);
+        assert_eq!(parse(b"\0"), [OsStr::new("")]);
+        assert_eq!(parse(b"\0\0"), [OsStr::new(""), OsStr::new("")]);
+        assert_eq!(parse(b"a"), [OsStr::new("a")]);
+        assert_eq!(parse(b"a\0"), [OsStr::new("a")]);
+        assert_eq!(
+            parse(b"ls\0-l\0\0a\xffb\0"),
+            [
+                OsStr::new("ls"),
+                OsStr::new("-l"),
+                OsStr::new(""),
+                OsStr::from_bytes(b"a\xffb")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reader_writer() {
+        let entries = [OsStr::new("a"), OsStr::new(""), OsStr::from_bytes(b"b\xff")];
+
+        let mut writer = NulSeparatedWriter::new(Vec::new());
+        for entry in entries.iter() {
+            writer.write_entry(entry).unwrap();
+        }
+        assert_eq!(
+            writer.write_entry(OsStr::new("a\0b")).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+        let buf = writer.into_inner();
+        assert_eq!(buf, b"a\0\0b\xff\0");
+
+        assert_eq!(parse(&buf), entries);
+
+        let read = NulSeparatedReader::new(&buf[..])
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(read, entries);
+
+        // Missing final terminator
+        let read = NulSeparatedReader::new(&b"a\0b"[..])
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(read, [OsStr::new("a"), OsStr::new("b")]);
+    }
+
+    #[test]
+    fn test_cstring_array() {
+        let mut arr = CStringArray::new();
+        assert!(arr.is_empty());
+        assert!(unsafe { *arr.as_ptr() }.is_null());
+
+        for s in ["ls", "-l", "/"].iter() {
+            arr.push(OsStr::new(s)).unwrap();
+        }
+        assert_eq!(arr.push(OsStr::new("a\0")).unwrap_err().nul_position(), 1);
+        assert_eq!(arr.len(), 3);
+
+        let ptr = arr.as_ptr();
+        let strings: Vec<&CStr> = (0..)
+            .map(|i| unsafe { *ptr.add(i) })
+            .take_while(|p| !p.is_null())
+            .map(|p| unsafe { CStr::from_ptr(p) })
+            .collect();
+        assert_eq!(strings, arr.iter().collect::<Vec<_>>());
+        assert_eq!(
+            strings,
+            [
+                CStr::from_bytes_with_nul(b"ls\0").unwrap(),
+                CStr::from_bytes_with_nul(b"-l\0").unwrap(),
+                CStr::from_bytes_with_nul(b"/\0").unwrap(),
+            ]
+        );
+    }
+}
@@ -0,0 +1,175 @@
+//! Searching many `OsStr`s in parallel with `rayon`.
+//!
+//! Results are always in the same order as the items they came from.
+
+use std::borrow::Cow;
+use std::ffi::OsStr;
+
+use rayon::prelude::*;
+
+use crate::finder::OsStrFinder;
+use crate::OsStrExt2;
+
+/// Something that can be used as an [`OsStrFinder`]: either a shared finder, or a needle to build
+/// one from.
+pub trait IntoFinder<'f> {
+    fn into_finder(self) -> Cow<'f, OsStrFinder>;
+}
+
+impl<'f> IntoFinder<'f> for &'f OsStrFinder {
+    #[inline]
+    fn into_finder(self) -> Cow<'f, OsStrFinder> {
+        Cow::Borrowed(self)
+    }
+}
+
+impl<'f> IntoFinder<'f> for &OsStr {
+    #[inline]
+    fn into_finder(self) -> Cow<'f, OsStrFinder> {
+        Cow::Owned(OsStrFinder::new(self))
+    }
+}
+
+/// Get the items that contain the needle.
+pub fn par_filter_matching<'a, 'f, S, F>(items: &'a [S], finder: F) -> Vec<&'a OsStr>
+where
+    S: AsRef<OsStr> + Sync,
+    F: IntoFinder<'f>,
+{
+    let finder = finder.into_finder();
+
+    items
+        .par_iter()
+        .map(AsRef::as_ref)
+        .filter(|item| finder.is_match(item))
+        .collect()
+}
+
+/// Find every occurrence of the needle in every item, as `(item index, offset)` pairs.
+///
+/// Offsets are in the same units as [`OsStrExt2::find()`], and overlapping matches are included
+/// (like [`OsStrExt2::find_all()`]).
+pub fn par_find_all<'f, S, F>(items: &[S], finder: F) -> Vec<(usize, usize)>
+where
+    S: AsRef<OsStr> + Sync,
+    F: IntoFinder<'f>,
+{
+    let finder = finder.into_finder();
+    let finder = &*finder;
+
+    items
+        .par_iter()
+        .enumerate()
+        .flat_map_iter(|(i, item)| {
+            finder
+                .find_all(item.as_ref())
+                .map(move |offset| (i, offset))
+        })
+        .collect()
+}
+
+/// Sort the items into groups by which of `prefixes` they start with.
+///
+/// The result has one group per prefix. If an item starts with more than one of the prefixes, it
+/// goes in the group of the longest one (or the first of the longest ones); items that don't start
+/// with any of them are left out.
+pub fn par_group_by_prefix<'a, S, P>(items: &'a [S], prefixes: &[P]) -> Vec<Vec<&'a OsStr>>
+where
+    S: AsRef<OsStr> + Sync,
+    P: AsRef<OsStr> + Sync,
+{
+    let groups: Vec<Option<usize>> = items
+        .par_iter()
+        .map(|item| {
+            let item = item.as_ref();
+            prefixes
+                .iter()
+                .enumerate()
+                .filter(|(_, prefix)| item.starts_with(prefix.as_ref()))
+                // `max_by_key()` picks the last maximum, so go backwards to get the first one
+                .rev()
+                .max_by_key(|(_, prefix)| prefix.as_ref().len())
+                .map(|(i, _)| i)
+        })
+        .collect();
+
+    let mut res = vec![Vec::new(); prefixes.len()];
+    for (item, group) in items.iter().zip(groups) {
+        if let Some(group) = group {
+            res[group].push(item.as_ref());
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::OsString;
+
+    fn items() -> Vec<OsString> {
+        (0..1000)
+            .map(|i| OsString::from(format!("/dir{}/file{}", i % 7, i)))
+            .collect()
+    }
+
+    #[test]
+    fn test_filter_matching() {
+        let items = items();
+        let needle = OsStr::new("file1");
+
+        let expected: Vec<&OsStr> = items
+            .iter()
+            .map(OsString::as_os_str)
+            .filter(|item| item.find(needle).is_some())
+            .collect();
+
+        assert_eq!(par_filter_matching(&items, needle), expected);
+        assert_eq!(
+            par_filter_matching(&items, &OsStrFinder::new(needle)),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_find_all() {
+        let items = items();
+        let finder = OsStrFinder::new(OsStr::new("1"));
+
+        let expected: Vec<(usize, usize)> = items
+            .iter()
+            .enumerate()
+            .flat_map(|(i, item)| item.find_all(OsStr::new("1")).map(move |off| (i, off)))
+            .collect();
+
+        assert_eq!(par_find_all(&items, &finder), expected);
+        assert_eq!(par_find_all(&["aaa"], OsStr::new("aa")), [(0, 0), (0, 1)]);
+    }
+
+    #[test]
+    fn test_group_by_prefix() {
+        let items = [
+            "/usr/bin/ls",
+            "/etc/passwd",
+            "/usr/lib/libc.so",
+            "/usr/bin/cat",
+            "/tmp",
+        ];
+        let prefixes = ["/usr/", "/usr/bin/", "/etc/", "/var/"];
+
+        assert_eq!(
+            par_group_by_prefix(&items, &prefixes),
+            [
+                vec![OsStr::new("/usr/lib/libc.so")],
+                vec![OsStr::new("/usr/bin/ls"), OsStr::new("/usr/bin/cat")],
+                vec![OsStr::new("/etc/passwd")],
+                vec![],
+            ]
+        );
+
+        assert_eq!(
+            par_group_by_prefix(&items, &["/", "/"]),
+            [items.iter().map(OsStr::new).collect::<Vec<_>>(), vec![]]
+        );
+    }
+}
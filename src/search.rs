@@ -0,0 +1,386 @@
+//! Substring search over plain slices of code units.
+//!
+//! This is what [`OsStrExt2`](crate::OsStrExt2) uses internally, and it works on any platform,
+//! so (for example) UTF-16 buffers can be searched on Unix as well.
+
+use std::iter::FusedIterator;
+
+mod sealed {
+    pub trait Sealed {}
+
+    impl Sealed for u8 {}
+    impl Sealed for u16 {}
+}
+
+/// A code unit type that can be searched (`u8` or `u16`).
+pub trait SearchUnit: Copy + Eq + sealed::Sealed {
+    #[doc(hidden)]
+    fn find_in(haystack: &[Self], needle: &[Self]) -> Option<usize>;
+    #[doc(hidden)]
+    fn rfind_in(haystack: &[Self], needle: &[Self]) -> Option<usize>;
+}
+
+impl SearchUnit for u8 {
+    #[inline]
+    fn find_in(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+        find_bytes(haystack, needle)
+    }
+
+    #[inline]
+    fn rfind_in(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+        rfind_bytes(haystack, needle)
+    }
+}
+
+impl SearchUnit for u16 {
+    #[inline]
+    fn find_in(haystack: &[u16], needle: &[u16]) -> Option<usize> {
+        find_wide(haystack, needle)
+    }
+
+    #[inline]
+    fn rfind_in(haystack: &[u16], needle: &[u16]) -> Option<usize> {
+        rfind_wide(haystack, needle)
+    }
+}
+
+/// Find the first occurrence of `needle` in `haystack`.
+#[inline]
+pub fn find<T: SearchUnit>(haystack: &[T], needle: &[T]) -> Option<usize> {
+    T::find_in(haystack, needle)
+}
+
+/// Find the last occurrence of `needle` in `haystack`.
+#[inline]
+pub fn rfind<T: SearchUnit>(haystack: &[T], needle: &[T]) -> Option<usize> {
+    T::rfind_in(haystack, needle)
+}
+
+/// Iterate over all the (possibly overlapping) occurrences of `needle` in `haystack`.
+#[inline]
+pub fn find_all<'a, T: SearchUnit>(haystack: &'a [T], needle: &'a [T]) -> CodeUnitSearch<'a, T> {
+    CodeUnitSearch::new(haystack, needle)
+}
+
+/// An iterator over the offsets of the occurrences of a needle in a slice of code units, from
+/// either end.
+///
+/// Like [`OsStrExt2::find_all()`](crate::OsStrExt2::find_all), this finds overlapping matches,
+/// and an empty needle matches at every offset (including the end).
+#[derive(Clone, Debug)]
+pub struct CodeUnitSearch<'a, T> {
+    haystack: &'a [T],
+    needle: &'a [T],
+    state: SearchState,
+}
+
+impl<'a, T: SearchUnit> CodeUnitSearch<'a, T> {
+    #[inline]
+    pub fn new(haystack: &'a [T], needle: &'a [T]) -> Self {
+        Self {
+            haystack,
+            needle,
+            state: SearchState::new(haystack.len(), needle.len()),
+        }
+    }
+}
+
+impl<T: SearchUnit> Iterator for CodeUnitSearch<'_, T> {
+    type Item = usize;
+
+    #[inline]
+    fn next(&mut self) -> Option<usize> {
+        self.state.next(self.haystack, self.needle)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.state.size_hint()
+    }
+}
+
+impl<T: SearchUnit> DoubleEndedIterator for CodeUnitSearch<'_, T> {
+    #[inline]
+    fn next_back(&mut self) -> Option<usize> {
+        self.state.next_back(self.haystack, self.needle)
+    }
+}
+
+impl<T: SearchUnit> FusedIterator for CodeUnitSearch<'_, T> {}
+
+// The bounds of a double-ended search. This is kept separate from the haystack and needle so that
+// iterators that have to own them (because they had to be re-encoded) can share it.
+#[derive(Clone, Debug)]
+pub(crate) struct SearchState {
+    // Matches can start anywhere in `left..right`
+    left: usize,
+    right: usize,
+}
+
+impl SearchState {
+    pub(crate) fn new(haystack_len: usize, needle_len: usize) -> Self {
+        let (left, right) = if let Some(diff) = haystack_len.checked_sub(needle_len) {
+            // Add 1 to the right bound; this allow matching on the very last element
+            (0, diff + 1)
+        } else {
+            // Needle is longer than haystack -> force immediate failure
+            (1, 0)
+        };
+
+        Self { left, right }
+    }
+
+    pub(crate) fn next<T: SearchUnit>(&mut self, haystack: &[T], needle: &[T]) -> Option<usize> {
+        if self.left >= self.right {
+            return None;
+        } else if needle.is_empty() {
+            // An empty needle matches the whole way through
+            let index = self.left;
+            self.left += 1;
+            return Some(index);
+        }
+
+        match T::find_in(&haystack[self.left..self.right + needle.len() - 1], needle) {
+            Some(index) => {
+                let index = self.left + index;
+                // Update for next time
+                self.left = index + 1;
+                Some(index)
+            }
+
+            None => {
+                // Force immediate return next time
+                self.left = self.right + 1;
+                None
+            }
+        }
+    }
+
+    pub(crate) fn next_back<T: SearchUnit>(
+        &mut self,
+        haystack: &[T],
+        needle: &[T],
+    ) -> Option<usize> {
+        if self.left >= self.right {
+            return None;
+        } else if needle.is_empty() {
+            // An empty needle matches the whole way through
+            self.right -= 1;
+            return Some(self.right);
+        }
+
+        match T::rfind_in(&haystack[self.left..self.right + needle.len() - 1], needle) {
+            Some(index) => {
+                let index = self.left + index;
+                // Update for next time
+                self.right = index;
+                Some(index)
+            }
+
+            None => {
+                // Force immediate return next time
+                self.left = self.right + 1;
+                None
+            }
+        }
+    }
+
+    #[inline]
+    pub(crate) fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.right.saturating_sub(self.left)))
+    }
+}
+
+// Byte search, used directly on Unix and for WTF-8 on Windows
+#[cfg(feature = "twoway")]
+#[inline]
+pub(crate) fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    twoway::find_bytes(haystack, needle)
+}
+
+#[cfg(not(feature = "twoway"))]
+pub(crate) fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    #[cfg(feature = "memchr")]
+    let indices = match needle.len() {
+        0 => return Some(0),
+        1 => return memchr::memchr(needle[0], haystack),
+        len => memchr::memchr_iter(needle[0], &haystack[..haystack.len().checked_sub(len)? + 1]),
+    };
+
+    #[cfg(not(feature = "memchr"))]
+    let indices = match needle.len() {
+        0 => return Some(0),
+        1 => {
+            let search_ch = needle[0];
+            return haystack.iter().position(|&ch| ch == search_ch);
+        }
+        len => 0..=(haystack.len().checked_sub(len)?),
+    };
+
+    for i in indices {
+        if &haystack[i..i + needle.len()] == needle {
+            return Some(i);
+        }
+    }
+
+    None
+}
+
+#[cfg(feature = "twoway")]
+#[inline]
+pub(crate) fn rfind_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    twoway::rfind_bytes(haystack, needle)
+}
+
+#[cfg(not(feature = "twoway"))]
+pub(crate) fn rfind_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    #[cfg(feature = "memchr")]
+    let indices = match needle.len() {
+        0 => return Some(haystack.len()),
+        1 => return memchr::memrchr(needle[0], haystack),
+        len => memchr::memrchr_iter(needle[0], &haystack[..haystack.len().checked_sub(len)? + 1]),
+    };
+
+    #[cfg(not(feature = "memchr"))]
+    let indices = match needle.len() {
+        0 => return Some(haystack.len()),
+        1 => {
+            let search_ch = needle[0];
+            return haystack.iter().rposition(|&ch| ch == search_ch);
+        }
+        len => (0..=(haystack.len().checked_sub(len)?)).rev(),
+    };
+
+    for i in indices {
+        if &haystack[i..i + needle.len()] == needle {
+            return Some(i);
+        }
+    }
+
+    None
+}
+
+// With memchr, search the underlying bytes with memmem, skipping any matches that aren't aligned
+// to a code unit
+#[cfg(feature = "memchr")]
+fn find_wide(haystack: &[u16], needle: &[u16]) -> Option<usize> {
+    let (haystack, needle) = (wide_bytes(haystack), wide_bytes(needle));
+
+    let mut pos = 0;
+    while let Some(index) = memchr::memmem::find(&haystack[pos..], needle) {
+        let index = pos + index;
+        if index % 2 == 0 {
+            return Some(index / 2);
+        }
+        pos = index + 1;
+    }
+
+    None
+}
+
+#[cfg(feature = "memchr")]
+fn rfind_wide(haystack: &[u16], needle: &[u16]) -> Option<usize> {
+    let (haystack, needle) = (wide_bytes(haystack), wide_bytes(needle));
+
+    let mut end = haystack.len();
+    while let Some(index) = memchr::memmem::rfind(&haystack[..end], needle) {
+        if index % 2 == 0 {
+            return Some(index / 2);
+        }
+        end = index + needle.len() - 1;
+    }
+
+    None
+}
+
+#[cfg(feature = "memchr")]
+#[inline]
+fn wide_bytes(units: &[u16]) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(units.as_ptr().cast(), std::mem::size_of_val(units)) }
+}
+
+// Otherwise, look for the first unit, then make sure that the rest match
+#[cfg(not(feature = "memchr"))]
+fn find_wide(haystack: &[u16], needle: &[u16]) -> Option<usize> {
+    let (first, rest) = match needle.split_first() {
+        Some(split) => split,
+        None => return Some(0),
+    };
+
+    (0..=haystack.len().checked_sub(needle.len())?)
+        .find(|&i| haystack[i] == *first && haystack[i + 1..i + needle.len()] == *rest)
+}
+
+#[cfg(not(feature = "memchr"))]
+fn rfind_wide(haystack: &[u16], needle: &[u16]) -> Option<usize> {
+    let (first, rest) = match needle.split_first() {
+        Some(split) => split,
+        None => return Some(haystack.len()),
+    };
+
+    (0..=haystack.len().checked_sub(needle.len())?)
+        .rev()
+        .find(|&i| haystack[i] == *first && haystack[i + 1..i + needle.len()] == *rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check<T: SearchUnit + std::fmt::Debug>(haystack: &[T], needle: &[T]) {
+        let expected: Vec<usize> = (0..=haystack.len())
+            .filter(|&i| haystack[i..].starts_with(needle))
+            .collect();
+
+        assert_eq!(find(haystack, needle), expected.first().copied());
+        assert_eq!(rfind(haystack, needle), expected.last().copied());
+        assert_eq!(find_all(haystack, needle).collect::<Vec<_>>(), expected);
+
+        let mut rev: Vec<usize> = find_all(haystack, needle).rev().collect();
+        rev.reverse();
+        assert_eq!(rev, expected);
+
+        // Alternate between the two ends
+        let mut it = find_all(haystack, needle);
+        let mut front = Vec::new();
+        let mut back = Vec::new();
+        while let Some(i) = it.next() {
+            front.push(i);
+            match it.next_back() {
+                Some(i) => back.push(i),
+                None => break,
+            }
+        }
+        assert_eq!(it.next(), None);
+        front.extend(back.into_iter().rev());
+        assert_eq!(front, expected);
+    }
+
+    #[test]
+    fn test_bytes() {
+        check(b"", b"");
+        check(b"abc", b"");
+        check(b"", b"a");
+        check(b"abcabca", b"a");
+        check(b"abcabca", b"bc");
+        check(b"aaaa", b"aa");
+        check(b"abc", b"abcd");
+    }
+
+    #[test]
+    fn test_wide() {
+        let wide = |s: &str| s.encode_utf16().collect::<Vec<u16>>();
+
+        check(&wide(""), &wide(""));
+        check(&wide("abc"), &wide(""));
+        check(&wide("abcabca"), &wide("a"));
+        check(&wide("aaaa"), &wide("aa"));
+        check(&wide("x\u{1f600}y\u{1f600}"), &wide("\u{1f600}"));
+        check::<u16>(&[0xD83D, 0xDE00, 0xD83D], &[0xD83D]);
+
+        // The bytes of these match at an odd offset, which has to be skipped
+        check::<u16>(&[0x0100, 0x0001, 0x0100], &[0x0101]);
+        check::<u16>(&[0x0100, 0x0001, 0x0100], &[0x0001, 0x0100]);
+        check::<u16>(&[0x6261, 0x6362, 0x6261, 0x6362], &[0x6362, 0x6261]);
+    }
+}
@@ -0,0 +1,251 @@
+//! Editing search path lists like `PATH`, `LD_LIBRARY_PATH` or `PYTHONPATH`.
+
+use std::collections::HashSet;
+use std::ffi::{OsStr, OsString};
+use std::path::Path;
+
+use crate::OsStrExt2;
+
+/// The separator used for search path lists on the current platform.
+#[cfg(unix)]
+pub const SEPARATOR: &str = ":";
+/// The separator used for search path lists on the current platform.
+#[cfg(windows)]
+pub const SEPARATOR: &str = ";";
+
+/// An ordered list of directories, like the value of `PATH`.
+///
+/// Unlike `std::env::split_paths()` and `std::env::join_paths()`, this keeps every entry exactly
+/// as it was (including invalid Unicode and empty entries), so a value can be edited and written
+/// back without changing anything else.
+///
+/// An empty entry means the current directory, so it's considered equal to `.` when comparing
+/// entries.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SearchPathList {
+    entries: Vec<OsString>,
+    sep: OsString,
+}
+
+impl SearchPathList {
+    /// Create an empty list that uses the platform's separator.
+    #[inline]
+    pub fn new() -> Self {
+        Self::with_separator(OsStr::new(SEPARATOR))
+    }
+
+    /// Create an empty list with a custom separator.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `sep` is empty.
+    pub fn with_separator(sep: &OsStr) -> Self {
+        assert!(!sep.is_empty(), "separator cannot be empty");
+
+        Self {
+            entries: Vec::new(),
+            sep: sep.into(),
+        }
+    }
+
+    /// Parse a list using the platform's separator.
+    ///
+    /// An empty string is parsed as an empty list (not as a list with one empty entry).
+    #[inline]
+    pub fn parse(value: &OsStr) -> Self {
+        Self::parse_with_separator(value, OsStr::new(SEPARATOR))
+    }
+
+    /// Parse a list using a custom separator.
+    pub fn parse_with_separator(value: &OsStr, sep: &OsStr) -> Self {
+        let mut list = Self::with_separator(sep);
+        if !value.is_empty() {
+            list.entries = value.split(sep).map(OsString::from).collect();
+        }
+        list
+    }
+
+    #[inline]
+    pub fn separator(&self) -> &OsStr {
+        &self.sep
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Iterate over the raw entries.
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = &OsStr> + '_ {
+        self.entries.iter().map(OsString::as_os_str)
+    }
+
+    /// Iterate over the entries as directories, with empty entries replaced by `.`.
+    #[inline]
+    pub fn dirs(&self) -> impl Iterator<Item = &Path> + '_ {
+        self.iter().map(|entry| Path::new(normalize(entry)))
+    }
+
+    #[inline]
+    pub fn contains(&self, entry: &OsStr) -> bool {
+        self.iter().any(|e| entries_equal(e, entry))
+    }
+
+    /// Add an entry at the start of the list, returning `false` (and leaving the list unchanged)
+    /// if it contains the separator.
+    pub fn prepend(&mut self, entry: &OsStr) -> bool {
+        if !self.is_valid_entry(entry) {
+            return false;
+        }
+        self.entries.insert(0, entry.into());
+        true
+    }
+
+    /// Add an entry at the end of the list, returning `false` (and leaving the list unchanged) if
+    /// it contains the separator.
+    pub fn append(&mut self, entry: &OsStr) -> bool {
+        if !self.is_valid_entry(entry) {
+            return false;
+        }
+        self.entries.push(entry.into());
+        true
+    }
+
+    /// Remove all occurrences of `entry`, returning whether anything was removed.
+    pub fn remove(&mut self, entry: &OsStr) -> bool {
+        let len = self.entries.len();
+        self.entries.retain(|e| !entries_equal(e, entry));
+        self.entries.len() != len
+    }
+
+    /// Remove duplicate entries, keeping the first occurrence of each.
+    pub fn dedup(&mut self) {
+        let keep: Vec<bool> = {
+            let mut seen = HashSet::new();
+            self.iter().map(|e| seen.insert(normalize(e))).collect()
+        };
+
+        let mut keep = keep.into_iter();
+        self.entries.retain(|_| keep.next().unwrap());
+    }
+
+    /// Join the entries back together with the separator.
+    pub fn to_os_string(&self) -> OsString {
+        let mut res = OsString::new();
+        for (i, entry) in self.entries.iter().enumerate() {
+            if i > 0 {
+                res.push(&self.sep);
+            }
+            res.push(entry);
+        }
+        res
+    }
+
+    #[inline]
+    fn is_valid_entry(&self, entry: &OsStr) -> bool {
+        entry.find(&self.sep).is_none()
+    }
+}
+
+impl Default for SearchPathList {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[inline]
+fn normalize(entry: &OsStr) -> &OsStr {
+    if entry.is_empty() {
+        OsStr::new(".")
+    } else {
+        entry
+    }
+}
+
+#[inline]
+fn entries_equal(a: &OsStr, b: &OsStr) -> bool {
+    normalize(a) == normalize(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> SearchPathList {
+        SearchPathList::parse_with_separator(OsStr::new(s), OsStr::new(":"))
+    }
+
+    #[test]
+    fn test_parse() {
+        assert!(parse("").is_empty());
+        assert_eq!(parse(":").iter().collect::<Vec<_>>(), ["", ""]);
+        assert_eq!(
+            parse("/bin::/usr/bin").iter().collect::<Vec<_>>(),
+            ["/bin", "", "/usr/bin"]
+        );
+        assert_eq!(
+            parse("/bin::/usr/bin").dirs().collect::<Vec<_>>(),
+            [Path::new("/bin"), Path::new("."), Path::new("/usr/bin")]
+        );
+
+        for &s in ["", ":", "/bin::/usr/bin", "a:b:"].iter() {
+            assert_eq!(parse(s).to_os_string(), OsStr::new(s));
+        }
+
+        let list = SearchPathList::parse_with_separator(OsStr::new("C:\\a;;D:"), OsStr::new(";"));
+        assert_eq!(list.iter().collect::<Vec<_>>(), ["C:\\a", "", "D:"]);
+        assert_eq!(list.separator(), ";");
+        assert_eq!(list.to_os_string(), "C:\\a;;D:");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_parse_invalid() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let value = OsStr::from_bytes(b"/a\xff:/b");
+        let list = SearchPathList::parse(value);
+        assert_eq!(
+            list.iter().collect::<Vec<_>>(),
+            [OsStr::from_bytes(b"/a\xff"), OsStr::new("/b")]
+        );
+        assert_eq!(list.to_os_string(), value);
+    }
+
+    #[test]
+    fn test_edit() {
+        let mut list = parse("/bin:.:/usr/bin::/bin");
+
+        assert!(list.prepend(OsStr::new("/opt/bin")));
+        assert!(list.append(OsStr::new("/sbin")));
+        assert_eq!(list.to_os_string(), "/opt/bin:/bin:.:/usr/bin::/bin:/sbin");
+
+        assert!(list.contains(OsStr::new("")));
+        assert!(list.contains(OsStr::new("/usr/bin")));
+        assert!(!list.contains(OsStr::new("/usr")));
+
+        list.dedup();
+        assert_eq!(list.to_os_string(), "/opt/bin:/bin:.:/usr/bin:/sbin");
+
+        assert!(list.remove(OsStr::new("")));
+        assert!(!list.remove(OsStr::new(".")));
+        assert!(list.remove(OsStr::new("/bin")));
+        assert_eq!(list.to_os_string(), "/opt/bin:/usr/bin:/sbin");
+        assert_eq!(list.len(), 3);
+    }
+
+    #[test]
+    fn test_add_separator() {
+        let mut list = parse("/bin");
+        assert!(!list.append(OsStr::new("/a:/b")));
+        assert!(!list.prepend(OsStr::new(":")));
+        assert_eq!(list.to_os_string(), "/bin");
+    }
+}
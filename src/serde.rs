@@ -0,0 +1,674 @@
+//! Modules for use with `#[serde(with = "...")]` on `OsString` fields.
+//!
+//! Every strategy serializes valid Unicode as a plain string, so the common case stays readable
+//! (and portable). They differ in how they represent strings that are *not* valid Unicode:
+//!
+//! - [`escaped`]: a map of the form `{"escaped": "..."}` holding an escaped string. Backslashes
+//!   are doubled, and invalid code units are written as `\xNN` (bytes on Unix) or `\uNNNN`
+//!   (unpaired surrogates on Windows).
+//! - [`base64`]: a map of the form `{"base64": "..."}` holding the WTF-8 bytes of the string (on
+//!   Unix, this is just the raw bytes).
+//! - [`chunks`]: an array whose elements are either strings (for valid runs) or arrays of code
+//!   units (for invalid runs).
+//!
+//! Telling these forms apart requires a self-describing format (JSON, YAML, TOML, ...). Formats
+//! that aren't human-readable, such as bincode and postcard, are usually not self-describing, so
+//! all the strategies just write the WTF-8 bytes of the string to them.
+//!
+//! Unpaired surrogates written on Windows are read on Unix as their WTF-8 bytes. Invalid bytes
+//! written on Unix can only be read on Windows if they happen to form valid WTF-8; otherwise,
+//! deserialization fails.
+//!
+//! Each module also has a `vec` submodule for `Vec<OsString>` fields.
+
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::marker::PhantomData;
+
+use ::serde::de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Unexpected, Visitor};
+use ::serde::ser::{SerializeMap, SerializeSeq, Serializer};
+use ::serde::{Deserialize, Serialize};
+
+use crate::{for_each_chunk, from_wtf8, push_escape, to_wtf8, Chunk, CodeUnit};
+
+// How each strategy represents strings in human-readable formats
+trait Strategy {
+    fn serialize<S: Serializer>(s: &OsStr, serializer: S) -> Result<S::Ok, S::Error>;
+    fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<OsString, D::Error>;
+}
+
+fn serialize_with<T: Strategy, S: Serializer>(s: &OsStr, serializer: S) -> Result<S::Ok, S::Error> {
+    if serializer.is_human_readable() {
+        T::serialize(s, serializer)
+    } else {
+        serializer.serialize_bytes(&to_wtf8(s))
+    }
+}
+
+fn deserialize_with<'de, T: Strategy, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<OsString, D::Error> {
+    if deserializer.is_human_readable() {
+        T::deserialize(deserializer)
+    } else {
+        deserializer.deserialize_byte_buf(Wtf8Visitor)
+    }
+}
+
+struct SerializeWith<'a, T>(&'a OsStr, PhantomData<T>);
+
+impl<T: Strategy> Serialize for SerializeWith<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_with::<T, S>(self.0, serializer)
+    }
+}
+
+struct DeserializeWith<T>(OsString, PhantomData<T>);
+
+impl<'de, T: Strategy> Deserialize<'de> for DeserializeWith<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_with::<T, D>(deserializer).map(|s| Self(s, PhantomData))
+    }
+}
+
+fn serialize_vec<T: Strategy, S: Serializer>(
+    v: &[OsString],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let mut seq = serializer.serialize_seq(Some(v.len()))?;
+    for s in v {
+        seq.serialize_element(&SerializeWith::<T>(s, PhantomData))?;
+    }
+    seq.end()
+}
+
+fn deserialize_vec<'de, T: Strategy, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<OsString>, D::Error> {
+    let v = Vec::<DeserializeWith<T>>::deserialize(deserializer)?;
+    Ok(v.into_iter().map(|s| s.0).collect())
+}
+
+macro_rules! with_module {
+    ($(#[$attr:meta])* $name:ident, $strategy:ident) => {
+        $(#[$attr])*
+        pub mod $name {
+            use super::*;
+
+            pub fn serialize<T, S>(s: &T, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                T: AsRef<OsStr> + ?Sized,
+                S: Serializer,
+            {
+                serialize_with::<$strategy, S>(s.as_ref(), serializer)
+            }
+
+            pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<OsString, D::Error> {
+                deserialize_with::<$strategy, D>(deserializer)
+            }
+
+            /// The same strategy, applied to each element of a `Vec<OsString>`.
+            pub mod vec {
+                use super::super::*;
+
+                pub fn serialize<S: Serializer>(v: &[OsString], serializer: S) -> Result<S::Ok, S::Error> {
+                    serialize_vec::<$strategy, S>(v, serializer)
+                }
+
+                pub fn deserialize<'de, D: Deserializer<'de>>(
+                    deserializer: D,
+                ) -> Result<Vec<OsString>, D::Error> {
+                    deserialize_vec::<$strategy, D>(deserializer)
+                }
+            }
+        }
+    };
+}
+
+with_module!(
+    /// Fall back to `{"escaped": "..."}`.
+    escaped,
+    Escaped
+);
+with_module!(
+    /// Fall back to `{"base64": "..."}`.
+    base64,
+    Base64
+);
+with_module!(
+    /// Fall back to an array of string and code unit chunks.
+    chunks,
+    Chunks
+);
+
+// Converts the WTF-8 bytes that every strategy decodes to, failing if they came from Unix and
+// can't be represented on Windows
+fn finish_wtf8<E: de::Error>(bytes: Vec<u8>) -> Result<OsString, E> {
+    from_wtf8(bytes).ok_or_else(|| {
+        E::custom("string is not valid WTF-8, so it can't be represented on this platform")
+    })
+}
+
+// Appends a code unit from either platform: bytes (from Unix) as-is, and unpaired surrogates
+// (from Windows) as WTF-8
+fn push_foreign_unit(bytes: &mut Vec<u8>, unit: u16) -> bool {
+    match unit {
+        0..=0xFF => bytes.push(unit as u8),
+        0xD800..=0xDFFF => bytes.extend_from_slice(&[
+            0xE0 | (unit >> 12) as u8,
+            0x80 | ((unit >> 6) & 0x3F) as u8,
+            0x80 | (unit & 0x3F) as u8,
+        ]),
+        _ => return false,
+    }
+    true
+}
+
+// The byte form used for formats that aren't human-readable
+struct Wtf8Visitor;
+
+impl<'de> Visitor<'de> for Wtf8Visitor {
+    type Value = OsString;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("WTF-8 bytes")
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<OsString, E> {
+        finish_wtf8(v.into())
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<OsString, E> {
+        finish_wtf8(v)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<OsString, A::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+        finish_wtf8(bytes)
+    }
+}
+
+// Reads the value of a map whose only entry is `key[0]`
+fn visit_tagged<'de, A: MapAccess<'de>>(
+    mut map: A,
+    key: &'static [&'static str; 1],
+    visitor: &dyn de::Expected,
+) -> Result<String, A::Error> {
+    match map.next_key::<String>()? {
+        Some(ref k) if k == key[0] => (),
+        Some(k) => return Err(de::Error::unknown_field(&k, key)),
+        None => return Err(de::Error::missing_field(key[0])),
+    }
+
+    let value = map.next_value()?;
+    if map.next_key::<String>()?.is_some() {
+        return Err(de::Error::invalid_length(2, visitor));
+    }
+    Ok(value)
+}
+
+struct Escaped;
+
+fn parse_hex(s: &str, ndigits: usize) -> Option<u16> {
+    let digits = s.get(..ndigits)?;
+    if digits.bytes().all(|ch| ch.is_ascii_hexdigit()) {
+        u16::from_str_radix(digits, 16).ok()
+    } else {
+        None
+    }
+}
+
+fn unescape(s: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s;
+
+    while let Some(index) = rest.find('\\') {
+        bytes.extend_from_slice(&rest.as_bytes()[..index]);
+        let esc = &rest[index + 1..];
+
+        let esc_len = match esc.as_bytes().first()? {
+            b'\\' => {
+                bytes.push(b'\\');
+                1
+            }
+
+            b'x' => {
+                bytes.push(parse_hex(&esc[1..], 2)? as u8);
+                3
+            }
+
+            b'u' => {
+                let unit = parse_hex(&esc[1..], 4)?;
+                if !(0xD800..=0xDFFF).contains(&unit) {
+                    return None;
+                }
+                push_foreign_unit(&mut bytes, unit);
+                5
+            }
+
+            _ => return None,
+        };
+
+        rest = &esc[esc_len..];
+    }
+
+    bytes.extend_from_slice(rest.as_bytes());
+    Some(bytes)
+}
+
+impl Strategy for Escaped {
+    fn serialize<S: Serializer>(s: &OsStr, serializer: S) -> Result<S::Ok, S::Error> {
+        if let Some(s) = s.to_str() {
+            return serializer.serialize_str(s);
+        }
+
+        let mut out = String::new();
+        for_each_chunk(s, |chunk| match chunk {
+            Chunk::Valid(s) => {
+                for ch in s.chars() {
+                    if ch == '\\' {
+                        out.push_str("\\\\");
+                    } else {
+                        out.push(ch);
+                    }
+                }
+            }
+            Chunk::Invalid(units) => {
+                for &unit in units {
+                    push_escape(&mut out, unit);
+                }
+            }
+        });
+
+        let mut map = serializer.serialize_map(Some(1))?;
+        map.serialize_entry("escaped", &out)?;
+        map.end()
+    }
+
+    fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<OsString, D::Error> {
+        struct EscapedVisitor;
+
+        impl<'de> Visitor<'de> for EscapedVisitor {
+            type Value = OsString;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a string or a map with a single \"escaped\" key")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<OsString, E> {
+                Ok(v.into())
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<OsString, A::Error> {
+                let data = visit_tagged(map, &["escaped"], &self)?;
+                let bytes = unescape(&data).ok_or_else(|| {
+                    de::Error::invalid_value(Unexpected::Str(&data), &"an escaped string")
+                })?;
+                finish_wtf8(bytes)
+            }
+        }
+
+        deserializer.deserialize_any(EscapedVisitor)
+    }
+}
+
+struct Base64;
+
+const BASE64_CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_CHARS[(n >> (18 - 6 * i)) as usize & 0x3F] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+
+    out
+}
+
+fn base64_decode(s: &str) -> Option<Vec<u8>> {
+    let s = s.as_bytes();
+    if s.len() % 4 != 0 {
+        return None;
+    }
+
+    let nchunks = s.len() / 4;
+    let mut out = Vec::with_capacity(nchunks * 3);
+
+    for (i, chunk) in s.chunks(4).enumerate() {
+        let padding = chunk.iter().rev().take_while(|&&ch| ch == b'=').count();
+        // Padding is only allowed at the very end
+        if padding > 2 || (padding > 0 && i != nchunks - 1) {
+            return None;
+        }
+
+        let mut n = 0u32;
+        for (j, &ch) in chunk[..4 - padding].iter().enumerate() {
+            let value = match ch {
+                b'A'..=b'Z' => ch - b'A',
+                b'a'..=b'z' => ch - b'a' + 26,
+                b'0'..=b'9' => ch - b'0' + 52,
+                b'+' => 62,
+                b'/' => 63,
+                _ => return None,
+            };
+            n |= (value as u32) << (18 - 6 * j);
+        }
+
+        for j in 0..3 - padding {
+            out.push((n >> (16 - 8 * j)) as u8);
+        }
+    }
+
+    Some(out)
+}
+
+impl Strategy for Base64 {
+    fn serialize<S: Serializer>(s: &OsStr, serializer: S) -> Result<S::Ok, S::Error> {
+        if let Some(s) = s.to_str() {
+            return serializer.serialize_str(s);
+        }
+
+        let mut map = serializer.serialize_map(Some(1))?;
+        map.serialize_entry("base64", &base64_encode(&to_wtf8(s)))?;
+        map.end()
+    }
+
+    fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<OsString, D::Error> {
+        struct Base64Visitor;
+
+        impl<'de> Visitor<'de> for Base64Visitor {
+            type Value = OsString;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a string or a map with a single \"base64\" key")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<OsString, E> {
+                Ok(v.into())
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<OsString, A::Error> {
+                let data = visit_tagged(map, &["base64"], &self)?;
+                let bytes = base64_decode(&data)
+                    .ok_or_else(|| de::Error::invalid_value(Unexpected::Str(&data), &"base64"))?;
+                finish_wtf8(bytes)
+            }
+        }
+
+        deserializer.deserialize_any(Base64Visitor)
+    }
+}
+
+struct Chunks;
+
+enum OwnedChunk {
+    Valid(String),
+    Invalid(Vec<CodeUnit>),
+}
+
+impl Serialize for OwnedChunk {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Valid(s) => serializer.serialize_str(s),
+            Self::Invalid(units) => units.serialize(serializer),
+        }
+    }
+}
+
+// Appends the WTF-8 bytes of a single chunk (a string or an array of code units) to the buffer
+struct ChunkSeed<'a>(&'a mut Vec<u8>);
+
+impl<'de> DeserializeSeed<'de> for ChunkSeed<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for ChunkSeed<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a string or an array of code units")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<(), E> {
+        self.0.extend_from_slice(v.as_bytes());
+        Ok(())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(unit) = seq.next_element::<u16>()? {
+            if !push_foreign_unit(self.0, unit) {
+                return Err(de::Error::invalid_value(
+                    Unexpected::Unsigned(unit.into()),
+                    &"a byte or an unpaired surrogate",
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Strategy for Chunks {
+    fn serialize<S: Serializer>(s: &OsStr, serializer: S) -> Result<S::Ok, S::Error> {
+        if let Some(s) = s.to_str() {
+            return serializer.serialize_str(s);
+        }
+
+        let mut chunks = Vec::new();
+        for_each_chunk(s, |chunk| match (chunk, chunks.last_mut()) {
+            // Merge adjacent invalid sequences
+            (Chunk::Invalid(units), Some(OwnedChunk::Invalid(last))) => {
+                last.extend_from_slice(units)
+            }
+            (Chunk::Invalid(units), _) => chunks.push(OwnedChunk::Invalid(units.into())),
+            (Chunk::Valid(s), _) => chunks.push(OwnedChunk::Valid(s.into())),
+        });
+
+        chunks.serialize(serializer)
+    }
+
+    fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<OsString, D::Error> {
+        struct ChunksVisitor;
+
+        impl<'de> Visitor<'de> for ChunksVisitor {
+            type Value = OsString;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a string or an array of chunks")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<OsString, E> {
+                Ok(v.into())
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<OsString, A::Error> {
+                let mut bytes = Vec::new();
+                while seq.next_element_seed(ChunkSeed(&mut bytes))?.is_some() {}
+                finish_wtf8(bytes)
+            }
+        }
+
+        deserializer.deserialize_any(ChunksVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Data {
+        #[serde(with = "escaped")]
+        escaped: OsString,
+        #[serde(with = "base64")]
+        base64: OsString,
+        #[serde(with = "chunks")]
+        chunks: OsString,
+        #[serde(with = "escaped::vec")]
+        list: Vec<OsString>,
+    }
+
+    impl Data {
+        fn new(s: &OsStr) -> Self {
+            Self {
+                escaped: s.into(),
+                base64: s.into(),
+                chunks: s.into(),
+                list: vec![s.into(), OsString::new(), "abc".into()],
+            }
+        }
+    }
+
+    fn round_trip(s: &OsStr) -> String {
+        let data = Data::new(s);
+        let json = serde_json::to_string(&data).unwrap();
+        assert_eq!(serde_json::from_str::<Data>(&json).unwrap(), data);
+        json
+    }
+
+    #[test]
+    fn test_valid() {
+        assert_eq!(
+            round_trip(OsStr::new("abc")),
+            r#"{"escaped":"abc","base64":"abc","chunks":"abc","list":["abc","","abc"]}"#
+        );
+        assert_eq!(
+            round_trip(OsStr::new("a\\b")),
+            r#"{"escaped":"a\\b","base64":"a\\b","chunks":"a\\b","list":["a\\b","","abc"]}"#
+        );
+        round_trip(OsStr::new(""));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_invalid() {
+        use std::os::unix::ffi::OsStrExt;
+
+        assert_eq!(
+            round_trip(OsStr::from_bytes(b"a\xff\xfeb\\")),
+            r#"{"escaped":{"escaped":"a\\xff\\xfeb\\\\"},"base64":{"base64":"Yf/+Ylw="},"chunks":["a",[255,254],"b\\"],"list":[{"escaped":"a\\xff\\xfeb\\\\"},"","abc"]}"#
+        );
+        round_trip(OsStr::from_bytes(b"\x80"));
+        round_trip(OsStr::from_bytes(b"\xe2\x82"));
+    }
+
+    fn from_json<T: Strategy>(json: &str) -> Option<OsString> {
+        serde_json::from_str::<DeserializeWith<T>>(json)
+            .ok()
+            .map(|s| s.0)
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        assert_eq!(from_json::<Escaped>(r#""\\q""#).unwrap(), "\\q");
+        assert_eq!(
+            from_json::<Escaped>(r#"{"escaped":"a\\\\b"}"#).unwrap(),
+            "a\\b"
+        );
+        assert_eq!(from_json::<Escaped>(r#"{"escaped":"\\"}"#), None);
+        assert_eq!(from_json::<Escaped>(r#"{"escaped":"\\q"}"#), None);
+        assert_eq!(from_json::<Escaped>(r#"{"escaped":"\\x4"}"#), None);
+        assert_eq!(from_json::<Escaped>(r#"{"escaped":"\\u+123"}"#), None);
+        // Only unpaired surrogates are escaped with `\u`
+        assert_eq!(from_json::<Escaped>(r#"{"escaped":"\\u0041"}"#), None);
+        assert_eq!(from_json::<Escaped>(r#"{"base64":"YWI="}"#), None);
+
+        assert_eq!(from_json::<Base64>(r#"{"base64":"YW"}"#), None);
+        assert_eq!(from_json::<Base64>(r#"{"base64":"Y==="}"#), None);
+        assert_eq!(from_json::<Base64>(r#"{"bytes":"YWI="}"#), None);
+        assert_eq!(from_json::<Base64>(r#"{"base64":"YWI="}"#).unwrap(), "ab");
+
+        assert_eq!(from_json::<Chunks>(r#"[1, "a"]"#), None);
+        assert_eq!(from_json::<Chunks>(r#"[[256]]"#), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_from_windows() {
+        use std::os::unix::ffi::OsStrExt;
+
+        // Unpaired surrogates are read as their WTF-8 bytes
+        let expected = OsStr::from_bytes(b"a\xed\xa0\x80");
+        assert_eq!(
+            from_json::<Escaped>(r#"{"escaped":"a\\ud800"}"#).unwrap(),
+            expected
+        );
+        assert_eq!(
+            from_json::<Base64>(r#"{"base64":"Ye2ggA=="}"#).unwrap(),
+            expected
+        );
+        assert_eq!(from_json::<Chunks>(r#"["a",[55296]]"#).unwrap(), expected);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_from_unix() {
+        use std::os::windows::ffi::OsStringExt;
+
+        // Bytes that form WTF-8 can be converted, but others can't be represented
+        let expected = OsString::from_wide(&[b'a' as u16, 0xD800]);
+        assert_eq!(
+            from_json::<Escaped>(r#"{"escaped":"a\\xed\\xa0\\x80"}"#).unwrap(),
+            expected
+        );
+        assert_eq!(
+            from_json::<Chunks>(r#"["a",[237,160,128]]"#).unwrap(),
+            expected
+        );
+        assert_eq!(from_json::<Escaped>(r#"{"escaped":"a\\xff"}"#), None);
+        assert_eq!(from_json::<Base64>(r#"{"base64":"Yf8="}"#), None);
+        assert_eq!(from_json::<Chunks>(r#"["a",[255]]"#), None);
+    }
+
+    #[test]
+    fn test_binary() {
+        // Formats that aren't human-readable get the WTF-8 bytes for every strategy
+        let encoded = bincode::serialize(&SerializeWith::<Chunks>(OsStr::new("ab"), PhantomData));
+        assert_eq!(encoded.unwrap(), b"\x02\0\0\0\0\0\0\0ab");
+
+        let mut strings = vec![OsString::from("abc"), OsString::new()];
+        #[cfg(unix)]
+        strings.push(std::os::unix::ffi::OsStringExt::from_vec(
+            b"a\xff\xfeb\\".to_vec(),
+        ));
+        #[cfg(windows)]
+        strings.push(std::os::windows::ffi::OsStringExt::from_wide(&[
+            0x61, 0xD800,
+        ]));
+
+        for s in strings {
+            let data = Data::new(&s);
+            let encoded = bincode::serialize(&data).unwrap();
+            assert_eq!(bincode::deserialize::<Data>(&encoded).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_base64() {
+        for s in ["", "a", "ab", "abc", "abcd", "abcde"].iter() {
+            assert_eq!(
+                base64_decode(&base64_encode(s.as_bytes())).unwrap(),
+                s.as_bytes()
+            );
+        }
+        assert_eq!(base64_encode(b"abcd"), "YWJjZA==");
+    }
+}
@@ -0,0 +1,368 @@
+//! An `OsString` that stores short strings inline.
+
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// The longest string (in bytes of the platform's encoding) that is stored inline.
+pub const INLINE_CAP: usize = 23;
+
+/// An owned `OsStr` that stores strings of up to [`INLINE_CAP`] bytes inline, and only allocates
+/// for longer ones.
+///
+/// This derefs to `&OsStr`, and compares and hashes the same way.
+///
+/// On 64-bit targets, a `SmallOsString` is 32 bytes (an `OsString` is 24 on Unix), since the
+/// inline buffer needs a tag next to it. Each string that fits inline saves a heap allocation,
+/// which more than makes up for that, but a collection of mostly long strings will be larger
+/// than the equivalent `OsString`s.
+#[derive(Clone)]
+pub struct SmallOsString {
+    repr: Repr,
+}
+
+#[derive(Clone)]
+enum Repr {
+    Inline { len: u8, buf: [u8; INLINE_CAP] },
+    Heap(OsString),
+}
+
+impl SmallOsString {
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            repr: Repr::Inline {
+                len: 0,
+                buf: [0; INLINE_CAP],
+            },
+        }
+    }
+
+    #[inline]
+    pub fn as_os_str(&self) -> &OsStr {
+        match &self.repr {
+            // `buf` was filled from `encoded_bytes()`
+            Repr::Inline { len, buf } => unsafe {
+                crate::from_encoded_bytes(&buf[..usize::from(*len)])
+            },
+            Repr::Heap(s) => s,
+        }
+    }
+
+    /// Whether the string is stored inline (rather than on the heap).
+    #[inline]
+    pub fn is_inline(&self) -> bool {
+        matches!(self.repr, Repr::Inline { .. })
+    }
+
+    /// Convert to an `OsString`. This only allocates if the string is stored inline.
+    #[inline]
+    pub fn into_os_string(self) -> OsString {
+        match self.repr {
+            Repr::Inline { .. } => self.as_os_str().to_os_string(),
+            Repr::Heap(s) => s,
+        }
+    }
+
+    /// Append `s`, moving the string to the heap if it no longer fits inline.
+    pub fn push<S: AsRef<OsStr>>(&mut self, s: S) {
+        let s = s.as_ref();
+
+        match &mut self.repr {
+            Repr::Inline { len, buf }
+                if usize::from(*len) + s.len() <= INLINE_CAP
+                    && can_concat(&buf[..usize::from(*len)], crate::encoded_bytes(s)) =>
+            {
+                let start = usize::from(*len);
+                buf[start..start + s.len()].copy_from_slice(crate::encoded_bytes(s));
+                *len += s.len() as u8;
+            }
+
+            Repr::Heap(heap) => heap.push(s),
+
+            Repr::Inline { .. } => {
+                let mut joined = OsString::with_capacity(self.len() + s.len());
+                joined.push(self.as_os_str());
+                joined.push(s);
+                *self = joined.into();
+            }
+        }
+    }
+}
+
+// On Windows, joining the WTF-8 bytes isn't enough if they meet in the middle of a surrogate pair
+// (which `OsString::push()` handles)
+#[inline]
+fn can_concat(a: &[u8], b: &[u8]) -> bool {
+    cfg!(unix)
+        || !matches!(
+            (a, b),
+            ([.., 0xED, 0xA0..=0xAF, _], [0xED, 0xB0..=0xBF, ..])
+        )
+}
+
+impl Default for SmallOsString {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deref for SmallOsString {
+    type Target = OsStr;
+
+    #[inline]
+    fn deref(&self) -> &OsStr {
+        self.as_os_str()
+    }
+}
+
+impl AsRef<OsStr> for SmallOsString {
+    #[inline]
+    fn as_ref(&self) -> &OsStr {
+        self
+    }
+}
+
+impl AsRef<Path> for SmallOsString {
+    #[inline]
+    fn as_ref(&self) -> &Path {
+        Path::new(self.as_os_str())
+    }
+}
+
+impl Borrow<OsStr> for SmallOsString {
+    #[inline]
+    fn borrow(&self) -> &OsStr {
+        self
+    }
+}
+
+impl From<&OsStr> for SmallOsString {
+    fn from(s: &OsStr) -> Self {
+        let bytes = crate::encoded_bytes(s);
+
+        if bytes.len() <= INLINE_CAP {
+            let mut buf = [0; INLINE_CAP];
+            buf[..bytes.len()].copy_from_slice(bytes);
+            Self {
+                repr: Repr::Inline {
+                    len: bytes.len() as u8,
+                    buf,
+                },
+            }
+        } else {
+            Self {
+                repr: Repr::Heap(s.to_os_string()),
+            }
+        }
+    }
+}
+
+impl From<&str> for SmallOsString {
+    #[inline]
+    fn from(s: &str) -> Self {
+        OsStr::new(s).into()
+    }
+}
+
+/// Short strings are moved inline (freeing the allocation); longer ones keep their buffer.
+impl From<OsString> for SmallOsString {
+    #[inline]
+    fn from(s: OsString) -> Self {
+        if s.len() <= INLINE_CAP {
+            s.as_os_str().into()
+        } else {
+            Self {
+                repr: Repr::Heap(s),
+            }
+        }
+    }
+}
+
+impl From<String> for SmallOsString {
+    #[inline]
+    fn from(s: String) -> Self {
+        OsString::from(s).into()
+    }
+}
+
+impl From<PathBuf> for SmallOsString {
+    #[inline]
+    fn from(path: PathBuf) -> Self {
+        path.into_os_string().into()
+    }
+}
+
+impl From<SmallOsString> for OsString {
+    #[inline]
+    fn from(s: SmallOsString) -> Self {
+        s.into_os_string()
+    }
+}
+
+impl From<SmallOsString> for PathBuf {
+    #[inline]
+    fn from(s: SmallOsString) -> Self {
+        s.into_os_string().into()
+    }
+}
+
+impl PartialEq for SmallOsString {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.as_os_str() == other.as_os_str()
+    }
+}
+
+impl Eq for SmallOsString {}
+
+impl PartialEq<OsStr> for SmallOsString {
+    #[inline]
+    fn eq(&self, other: &OsStr) -> bool {
+        self.as_os_str() == other
+    }
+}
+
+impl PartialEq<str> for SmallOsString {
+    #[inline]
+    fn eq(&self, other: &str) -> bool {
+        self.as_os_str() == other
+    }
+}
+
+impl PartialOrd for SmallOsString {
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SmallOsString {
+    #[inline]
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_os_str().cmp(other.as_os_str())
+    }
+}
+
+impl Hash for SmallOsString {
+    #[inline]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_os_str().hash(state)
+    }
+}
+
+impl fmt::Debug for SmallOsString {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_os_str(), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::OsStrExt2;
+    use std::collections::hash_map::DefaultHasher;
+    use std::collections::{BTreeSet, HashSet};
+
+    fn hash<T: Hash + ?Sized>(t: &T) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        t.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_inline() {
+        let short = "a".repeat(INLINE_CAP);
+        let long = "a".repeat(INLINE_CAP + 1);
+
+        assert!(SmallOsString::new().is_inline());
+        assert_eq!(SmallOsString::new(), *"");
+
+        let s = SmallOsString::from(short.as_str());
+        assert!(s.is_inline());
+        assert_eq!(s, *short.as_str());
+
+        let s = SmallOsString::from(long.as_str());
+        assert!(!s.is_inline());
+        assert_eq!(s, *long.as_str());
+
+        // Long strings keep their buffer, short ones are moved inline
+        let os = OsString::from(long.clone());
+        let ptr = crate::encoded_bytes(&os).as_ptr();
+        let s = SmallOsString::from(os);
+        assert_eq!(crate::encoded_bytes(&s.into_os_string()).as_ptr(), ptr);
+        assert!(SmallOsString::from(PathBuf::from("a/b")).is_inline());
+
+        assert_eq!(PathBuf::from(SmallOsString::from("a/b")), Path::new("a/b"));
+    }
+
+    #[test]
+    fn test_push() {
+        let mut s = SmallOsString::new();
+        s.push("abc");
+        assert_eq!(s, *"abc");
+        assert!(s.is_inline());
+
+        for _ in 0..10 {
+            s.push("xyz");
+        }
+        assert!(!s.is_inline());
+        assert_eq!(s, *format!("abc{}", "xyz".repeat(10)).as_str());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_push_surrogates() {
+        use std::os::windows::ffi::{OsStrExt, OsStringExt};
+
+        // The halves of a surrogate pair are joined into a single character
+        let mut s = SmallOsString::from(OsString::from_wide(&[0x61, 0xD83D]));
+        s.push(OsString::from_wide(&[0xDE00, 0x62]));
+        assert!(s.is_inline());
+        assert_eq!(s, *"a\u{1F600}b");
+
+        let mut s = SmallOsString::from(OsString::from_wide(&[0xDE00]));
+        s.push(OsString::from_wide(&[0xD83D]));
+        assert!(s.is_inline());
+        assert_eq!(s.encode_wide().collect::<Vec<_>>(), [0xDE00, 0xD83D]);
+    }
+
+    #[test]
+    fn test_os_str_api() {
+        let s = SmallOsString::from("dir/file.txt");
+        assert_eq!(s.find(OsStr::new("/")), Some(3));
+        assert_eq!(s.split_once(OsStr::new("/")).unwrap().1, "file.txt");
+        assert_eq!(Path::new(&s).extension(), Some(OsStr::new("txt")));
+        assert_eq!(format!("{:?}", s), "\"dir/file.txt\"");
+    }
+
+    #[test]
+    fn test_hash_ord() {
+        let strs = ["", "a", "b", "ab", &"x".repeat(40), &"y".repeat(INLINE_CAP)];
+
+        for a in strs.iter() {
+            let small = SmallOsString::from(*a);
+            assert_eq!(hash(&small), hash(OsStr::new(a)));
+
+            for b in strs.iter() {
+                assert_eq!(
+                    small.cmp(&SmallOsString::from(*b)),
+                    OsStr::new(a).cmp(OsStr::new(b))
+                );
+            }
+        }
+
+        let set: HashSet<SmallOsString> = strs.iter().map(|&s| s.into()).collect();
+        assert!(set.contains(OsStr::new("ab")));
+        assert!(!set.contains(OsStr::new("abc")));
+
+        let set: BTreeSet<SmallOsString> = strs.iter().map(|&s| s.into()).collect();
+        assert!(set.contains(OsStr::new(&"x".repeat(40))));
+    }
+}
@@ -0,0 +1,351 @@
+//! Searching for an `OsStr` in a stream of bytes, without reading it all into memory.
+
+use std::ffi::OsStr;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::iter::FusedIterator;
+use std::os::unix::ffi::OsStrExt;
+
+use crate::search::{find_bytes, rfind_bytes};
+
+const DEFAULT_BUF_SIZE: usize = 64 * 1024;
+
+/// Searches readers for occurrences of a needle.
+///
+/// Like [`OsStrExt2::find_all()`](crate::OsStrExt2::find_all), this finds overlapping matches.
+#[derive(Clone, Debug)]
+pub struct StreamFinder {
+    needle: Vec<u8>,
+    buf_size: usize,
+}
+
+impl StreamFinder {
+    #[inline]
+    pub fn new(needle: &OsStr) -> Self {
+        Self {
+            needle: needle.as_bytes().to_vec(),
+            buf_size: DEFAULT_BUF_SIZE,
+        }
+    }
+
+    /// Set how much data is read at a time (64 KiB by default).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is 0.
+    #[inline]
+    pub fn with_buffer_size(mut self, size: usize) -> Self {
+        assert!(size > 0, "buffer size cannot be 0");
+        self.buf_size = size;
+        self
+    }
+
+    #[inline]
+    pub fn needle(&self) -> &OsStr {
+        OsStr::from_bytes(&self.needle)
+    }
+
+    /// Iterate over the offsets of the matches in `reader`, from the start.
+    ///
+    /// The offsets are relative to the position `reader` was at when it was passed in. Iteration
+    /// stops after the first error.
+    #[inline]
+    pub fn find_iter<R: Read>(&self, reader: R) -> StreamFindIter<'_, R> {
+        StreamFindIter {
+            finder: self,
+            reader,
+            buf: Vec::new(),
+            buf_offset: 0,
+            pos: 0,
+            eof: false,
+            done: false,
+        }
+    }
+
+    /// Iterate over the offsets of the matches in `reader`, starting from the end.
+    ///
+    /// Like [`find_iter()`](Self::find_iter), only the data after the position `reader` was at
+    /// when it was passed in is searched, and the offsets are relative to that position.
+    /// Iteration stops after the first error.
+    #[inline]
+    pub fn rfind_iter<R: Read + Seek>(&self, reader: R) -> StreamRFindIter<'_, R> {
+        StreamRFindIter {
+            finder: self,
+            reader,
+            buf: Vec::new(),
+            start: 0,
+            buf_offset: None,
+            limit: 0,
+            done: false,
+        }
+    }
+}
+
+/// The iterator returned by [`StreamFinder::find_iter()`].
+pub struct StreamFindIter<'a, R> {
+    finder: &'a StreamFinder,
+    reader: R,
+    buf: Vec<u8>,
+    // The offset in the stream of `buf[0]`
+    buf_offset: u64,
+    // Where to start searching in `buf`
+    pos: usize,
+    eof: bool,
+    done: bool,
+}
+
+impl<R> StreamFindIter<'_, R> {
+    #[inline]
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> StreamFindIter<'_, R> {
+    fn fill_buf(&mut self) -> io::Result<()> {
+        let needle_len = self.finder.needle.len();
+
+        // Keep anything that could be the start of a match that straddles the boundary
+        let keep_from = self
+            .pos
+            .max(self.buf.len().saturating_sub(needle_len.saturating_sub(1)))
+            .min(self.buf.len());
+        self.buf.drain(..keep_from);
+        self.buf_offset += keep_from as u64;
+        self.pos = 0;
+
+        let old_len = self.buf.len();
+        self.buf.resize(old_len + self.finder.buf_size, 0);
+        let res = loop {
+            match self.reader.read(&mut self.buf[old_len..]) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                res => break res,
+            }
+        };
+        let nread = *res.as_ref().unwrap_or(&0);
+        self.buf.truncate(old_len + nread);
+
+        if nread == 0 {
+            self.eof = true;
+        }
+        res.map(drop)
+    }
+}
+
+impl<R: Read> Iterator for StreamFindIter<'_, R> {
+    type Item = io::Result<u64>;
+
+    fn next(&mut self) -> Option<io::Result<u64>> {
+        let needle = &self.finder.needle;
+
+        while !self.done && self.pos <= self.buf.len() {
+            if let Some(index) = find_bytes(&self.buf[self.pos..], needle) {
+                let index = self.pos + index;
+                // An empty needle "matches" at the end of the buffer, but that's only the end of
+                // the stream at EOF
+                if !needle.is_empty() || index < self.buf.len() || self.eof {
+                    self.pos = index + 1;
+                    return Some(Ok(self.buf_offset + index as u64));
+                }
+            }
+
+            if self.eof {
+                break;
+            } else if let Err(e) = self.fill_buf() {
+                self.done = true;
+                return Some(Err(e));
+            }
+        }
+
+        self.done = true;
+        None
+    }
+}
+
+impl<R: Read> FusedIterator for StreamFindIter<'_, R> {}
+
+/// The iterator returned by [`StreamFinder::rfind_iter()`].
+pub struct StreamRFindIter<'a, R> {
+    finder: &'a StreamFinder,
+    reader: R,
+    buf: Vec<u8>,
+    // The position of the reader when the search started
+    start: u64,
+    // The offset of `buf[0]` from `start` (`None` until the length has been checked)
+    buf_offset: Option<u64>,
+    // Matches must start before this index in `buf`
+    limit: usize,
+    done: bool,
+}
+
+impl<R> StreamRFindIter<'_, R> {
+    #[inline]
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read + Seek> StreamRFindIter<'_, R> {
+    // Records where the search starts, and returns the length of the data after that
+    fn remaining_len(&mut self) -> io::Result<u64> {
+        self.start = self.reader.stream_position()?;
+        let end = self.reader.seek(SeekFrom::End(0))?;
+        Ok(end.saturating_sub(self.start))
+    }
+
+    // Reads the chunk before the buffer, returning `false` if the buffer is already at the start
+    fn fill_buf(&mut self, buf_offset: u64) -> io::Result<bool> {
+        if buf_offset == 0 {
+            return Ok(false);
+        }
+
+        let needle_len = self.finder.needle.len();
+        let chunk_len = (self.finder.buf_size as u64).min(buf_offset) as usize;
+        let start = buf_offset - chunk_len as u64;
+
+        // Only the start of the old buffer can be part of a match that straddles the boundary
+        self.buf
+            .truncate(needle_len.saturating_sub(1).min(self.buf.len()));
+        self.buf.splice(0..0, std::iter::repeat(0).take(chunk_len));
+
+        self.reader.seek(SeekFrom::Start(self.start + start))?;
+        self.reader.read_exact(&mut self.buf[..chunk_len])?;
+
+        self.buf_offset = Some(start);
+        self.limit = chunk_len;
+        Ok(true)
+    }
+}
+
+impl<R: Read + Seek> Iterator for StreamRFindIter<'_, R> {
+    type Item = io::Result<u64>;
+
+    fn next(&mut self) -> Option<io::Result<u64>> {
+        if self.done {
+            return None;
+        }
+
+        let needle = &self.finder.needle;
+
+        let mut buf_offset = match self.buf_offset {
+            Some(offset) => offset,
+            None => match self.remaining_len() {
+                Ok(len) => {
+                    self.buf_offset = Some(len);
+                    if needle.is_empty() {
+                        // The empty needle matches at the very end too
+                        return Some(Ok(len));
+                    }
+                    len
+                }
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            },
+        };
+
+        loop {
+            let end = (self.limit + needle.len())
+                .saturating_sub(1)
+                .min(self.buf.len());
+            if let Some(index) = rfind_bytes(&self.buf[..end], needle) {
+                if index < self.limit {
+                    self.limit = index;
+                    return Some(Ok(buf_offset + index as u64));
+                }
+            }
+
+            match self.fill_buf(buf_offset) {
+                Ok(true) => buf_offset = self.buf_offset.unwrap(),
+                Ok(false) => break,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+
+        self.done = true;
+        None
+    }
+}
+
+impl<R: Read + Seek> FusedIterator for StreamRFindIter<'_, R> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::OsStrExt2;
+    use std::io::Cursor;
+
+    fn check(haystack: &[u8], needle: &[u8]) {
+        let needle = OsStr::from_bytes(needle);
+        let expected: Vec<u64> = OsStr::from_bytes(haystack)
+            .find_all(needle)
+            .map(|i| i as u64)
+            .collect();
+
+        for &buf_size in [1, 2, 3, 5, 64].iter() {
+            let finder = StreamFinder::new(needle).with_buffer_size(buf_size);
+
+            let found = finder
+                .find_iter(haystack)
+                .collect::<io::Result<Vec<_>>>()
+                .unwrap();
+            assert_eq!(found, expected, "{:?} {:?} {}", haystack, needle, buf_size);
+
+            let mut found = finder
+                .rfind_iter(Cursor::new(haystack))
+                .collect::<io::Result<Vec<_>>>()
+                .unwrap();
+            found.reverse();
+            assert_eq!(found, expected, "{:?} {:?} {}", haystack, needle, buf_size);
+        }
+    }
+
+    #[test]
+    fn test_find_iter() {
+        check(b"", b"a");
+        check(b"abc", b"d");
+        check(b"abc", b"abcd");
+        check(b"abcabcab", b"abc");
+        check(b"aaaa", b"aa");
+        check(b"xx\xff\xfeyy\xff\xfe", b"\xff\xfe");
+        check(b"0123456789", b"456789");
+
+        check(b"", b"");
+        check(b"abc", b"");
+    }
+
+    #[test]
+    fn test_offsets() {
+        // Both directions only search after the starting position, and give offsets from there
+        let mut reader = Cursor::new(b"abc, abc abc".to_vec());
+        reader.seek(SeekFrom::Start(5)).unwrap();
+
+        let finder = StreamFinder::new(OsStr::new("abc")).with_buffer_size(2);
+        assert_eq!(finder.needle(), "abc");
+        let found: Vec<u64> = finder.find_iter(&mut reader).map(Result::unwrap).collect();
+        assert_eq!(found, [0, 4]);
+
+        reader.seek(SeekFrom::Start(5)).unwrap();
+        let found: Vec<u64> = finder.rfind_iter(&mut reader).map(Result::unwrap).collect();
+        assert_eq!(found, [4, 0]);
+    }
+
+    #[test]
+    fn test_error() {
+        struct Failing;
+
+        impl Read for Failing {
+            fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::other("boom"))
+            }
+        }
+
+        let finder = StreamFinder::new(OsStr::new("a"));
+        let mut iter = finder.find_iter(Failing);
+        assert!(iter.next().unwrap().is_err());
+        assert!(iter.next().is_none());
+    }
+}
@@ -0,0 +1,414 @@
+//! A prefix tree keyed by `OsStr`s, for longest-prefix lookups.
+
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::path::is_separator;
+
+use crate::{code_units, unit_to_ascii, CodeUnit};
+
+/// A map from `OsStr`s to values that can find the longest key that is a prefix of a string.
+///
+/// Keys are compared in the same units as
+/// [`OsStrExt2::starts_with()`](crate::OsStrExt2::starts_with) (bytes on Unix and UTF-16 code
+/// units on Windows), so they don't have to be valid Unicode.
+#[derive(Clone)]
+pub struct OsStrTrie<V> {
+    // `nodes[0]` is the root (the empty key)
+    nodes: Vec<Node<V>>,
+    // Slots in `nodes` that were pruned by `remove()`, to be reused by `insert()`
+    free: Vec<usize>,
+    len: usize,
+    components: bool,
+}
+
+#[derive(Clone)]
+struct Node<V> {
+    // Sorted by unit
+    children: Vec<(CodeUnit, usize)>,
+    entry: Option<(OsString, V)>,
+}
+
+impl<V> Node<V> {
+    fn new() -> Self {
+        Self {
+            children: Vec::new(),
+            entry: None,
+        }
+    }
+}
+
+impl<V> OsStrTrie<V> {
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            nodes: vec![Node::new()],
+            free: Vec::new(),
+            len: 0,
+            components: false,
+        }
+    }
+
+    /// Create a trie where keys only match whole path components.
+    ///
+    /// For example, `/a` is a prefix of `/a` and `/a/b`, but not `/ab`. Keys that end in a
+    /// separator (such as `/`) and the empty key still match anything they're a prefix of.
+    #[inline]
+    pub fn with_component_boundaries() -> Self {
+        Self {
+            components: true,
+            ..Self::new()
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Insert a value, returning the old value if `key` was already present.
+    pub fn insert(&mut self, key: &OsStr, value: V) -> Option<V> {
+        let mut node = 0;
+
+        for &unit in code_units(key).iter() {
+            node = match self.child(node, unit) {
+                Ok(child) => child,
+                Err(pos) => {
+                    let child = match self.free.pop() {
+                        Some(child) => child,
+                        None => {
+                            self.nodes.push(Node::new());
+                            self.nodes.len() - 1
+                        }
+                    };
+                    self.nodes[node].children.insert(pos, (unit, child));
+                    child
+                }
+            };
+        }
+
+        match &mut self.nodes[node].entry {
+            Some((_, old)) => Some(std::mem::replace(old, value)),
+            entry => {
+                *entry = Some((key.to_os_string(), value));
+                self.len += 1;
+                None
+            }
+        }
+    }
+
+    /// Get the value for exactly `key`.
+    #[inline]
+    pub fn get(&self, key: &OsStr) -> Option<&V> {
+        let node = self.find_node(&code_units(key))?;
+        self.nodes[node].entry.as_ref().map(|(_, value)| value)
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self, key: &OsStr) -> Option<&mut V> {
+        let node = self.find_node(&code_units(key))?;
+        self.nodes[node].entry.as_mut().map(|(_, value)| value)
+    }
+
+    #[inline]
+    pub fn contains_key(&self, key: &OsStr) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Remove `key`, returning its value if it was present.
+    ///
+    /// Nodes that no longer lead to any keys are pruned, and reused by later insertions.
+    pub fn remove(&mut self, key: &OsStr) -> Option<V> {
+        // The nodes along the way to `key`, starting with the root
+        let mut path = vec![0];
+        for &unit in code_units(key).iter() {
+            path.push(self.child(*path.last().unwrap(), unit).ok()?);
+        }
+
+        let (_, value) = self.nodes[*path.last().unwrap()].entry.take()?;
+        self.len -= 1;
+
+        while path.len() > 1 {
+            let node = path.pop().unwrap();
+            if self.nodes[node].entry.is_some() || !self.nodes[node].children.is_empty() {
+                break;
+            }
+
+            let parent = path.last().unwrap();
+            self.nodes[*parent]
+                .children
+                .retain(|&(_, child)| child != node);
+            self.free.push(node);
+        }
+
+        Some(value)
+    }
+
+    /// Find the longest key that is a prefix of `s` (in component-boundary mode, one that also
+    /// ends at a component boundary of `s`).
+    pub fn longest_prefix_of(&self, s: &OsStr) -> Option<(&OsStr, &V)> {
+        let units = code_units(s);
+        let mut best = None;
+        let mut node = 0;
+
+        for i in 0..=units.len() {
+            if let Some((key, value)) = &self.nodes[node].entry {
+                if !self.components || is_boundary(&units, i) {
+                    best = Some((key.as_os_str(), value));
+                }
+            }
+
+            node = match units.get(i).map(|&unit| self.child(node, unit)) {
+                Some(Ok(child)) => child,
+                _ => break,
+            };
+        }
+
+        best
+    }
+
+    /// Iterate over all the keys and values, sorted by key (in code unit order).
+    #[inline]
+    pub fn iter(&self) -> Iter<'_, V> {
+        Iter {
+            trie: self,
+            stack: vec![0],
+        }
+    }
+
+    /// Iterate over the keys that start with `prefix`, and their values, sorted by key.
+    ///
+    /// In component-boundary mode, `prefix` has to match whole components of the keys.
+    pub fn iter_prefix<'a>(&'a self, prefix: &OsStr) -> impl Iterator<Item = (&'a OsStr, &'a V)> {
+        let prefix = code_units(prefix);
+        let components = self.components;
+        let prefix_len = prefix.len();
+
+        Iter {
+            trie: self,
+            stack: self.find_node(&prefix).into_iter().collect(),
+        }
+        .filter(move |(key, _)| !components || is_boundary(&code_units(key), prefix_len))
+    }
+
+    // `Ok(child)`, or `Err(insertion point)` if there's no child for `unit`
+    #[inline]
+    fn child(&self, node: usize, unit: CodeUnit) -> Result<usize, usize> {
+        let children = &self.nodes[node].children;
+        children
+            .binary_search_by_key(&unit, |&(unit, _)| unit)
+            .map(|i| children[i].1)
+    }
+
+    fn find_node(&self, units: &[CodeUnit]) -> Option<usize> {
+        units
+            .iter()
+            .try_fold(0, |node, &unit| self.child(node, unit).ok())
+    }
+}
+
+impl<V> Default for OsStrTrie<V> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V: fmt::Debug> fmt::Debug for OsStrTrie<V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<'a, V> IntoIterator for &'a OsStrTrie<V> {
+    type Item = (&'a OsStr, &'a V);
+    type IntoIter = Iter<'a, V>;
+
+    #[inline]
+    fn into_iter(self) -> Iter<'a, V> {
+        self.iter()
+    }
+}
+
+impl<'a, V> Extend<(&'a OsStr, V)> for OsStrTrie<V> {
+    fn extend<I: IntoIterator<Item = (&'a OsStr, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+/// An iterator over the entries of an [`OsStrTrie`], sorted by key.
+#[derive(Clone, Debug)]
+pub struct Iter<'a, V> {
+    trie: &'a OsStrTrie<V>,
+    // Nodes still to visit, with the next one at the end
+    stack: Vec<usize>,
+}
+
+impl<'a, V> Iterator for Iter<'a, V> {
+    type Item = (&'a OsStr, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(node) = self.stack.pop() {
+            let node = &self.trie.nodes[node];
+            self.stack
+                .extend(node.children.iter().rev().map(|&(_, child)| child));
+
+            if let Some((key, value)) = &node.entry {
+                return Some((key.as_os_str(), value));
+            }
+        }
+
+        None
+    }
+}
+
+impl<V> std::iter::FusedIterator for Iter<'_, V> {}
+
+// Whether `units[..i]` ends at a component boundary
+fn is_boundary(units: &[CodeUnit], i: usize) -> bool {
+    let is_sep = |unit: &CodeUnit| unit_to_ascii(*unit).is_some_and(|ch| is_separator(ch as char));
+
+    i == 0 || i == units.len() || is_sep(&units[i - 1]) || units.get(i).is_some_and(is_sep)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_trie<'a>(components: bool, keys: &[&'a str]) -> OsStrTrie<&'a str> {
+        let mut trie = if components {
+            OsStrTrie::with_component_boundaries()
+        } else {
+            OsStrTrie::new()
+        };
+        trie.extend(keys.iter().map(|&key| (OsStr::new(key), key)));
+        trie
+    }
+
+    fn longest<'a>(trie: &'a OsStrTrie<&str>, s: &str) -> Option<&'a str> {
+        let (key, &value) = trie.longest_prefix_of(OsStr::new(s))?;
+        assert_eq!(key, value);
+        Some(value)
+    }
+
+    fn keys_with_prefix<'a>(trie: &'a OsStrTrie<&str>, prefix: &str) -> Vec<&'a str> {
+        trie.iter_prefix(OsStr::new(prefix))
+            .map(|(_, &value)| value)
+            .collect()
+    }
+
+    #[test]
+    fn test_insert_get() {
+        let mut trie = OsStrTrie::new();
+        assert!(trie.is_empty());
+        assert_eq!(trie.get(OsStr::new("")), None);
+
+        assert_eq!(trie.insert(OsStr::new("/usr"), 1), None);
+        assert_eq!(trie.insert(OsStr::new("/usr/lib"), 2), None);
+        assert_eq!(trie.insert(OsStr::new("/usr"), 3), Some(1));
+        assert_eq!(trie.len(), 2);
+
+        assert_eq!(trie.get(OsStr::new("/usr")), Some(&3));
+        assert_eq!(trie.get(OsStr::new("/us")), None);
+        assert_eq!(trie.get(OsStr::new("/usr/")), None);
+
+        *trie.get_mut(OsStr::new("/usr/lib")).unwrap() += 10;
+        assert_eq!(trie.remove(OsStr::new("/usr/lib")), Some(12));
+        assert_eq!(trie.remove(OsStr::new("/usr/lib")), None);
+        assert!(!trie.contains_key(OsStr::new("/usr/lib")));
+        assert_eq!(trie.len(), 1);
+
+        assert_eq!(format!("{:?}", trie), "{\"/usr\": 3}");
+    }
+
+    #[test]
+    fn test_remove_prunes() {
+        let mut trie = make_trie(false, &["/a", "/a/b"]);
+        let nodes = trie.nodes.len();
+
+        for i in 0..100 {
+            let key = format!("/a/{}", i);
+            trie.insert(OsStr::new(&key), "");
+            assert_eq!(trie.remove(OsStr::new(&key)), Some(""));
+        }
+        assert_eq!(trie.nodes.len(), nodes + 2);
+
+        assert_eq!(trie.remove(OsStr::new("/a/b")), Some("/a/b"));
+        assert_eq!(trie.nodes[0].children.len(), 1);
+        assert_eq!(longest(&trie, "/a/bc"), Some("/a"));
+        assert_eq!(keys_with_prefix(&trie, ""), ["/a"]);
+
+        assert_eq!(trie.remove(OsStr::new("/a")), Some("/a"));
+        assert!(trie.nodes[0].children.is_empty());
+        assert_eq!(trie.free.len(), trie.nodes.len() - 1);
+
+        trie.insert(OsStr::new("/a/b/c"), "/a/b/c");
+        assert_eq!(trie.nodes.len(), nodes + 2);
+        assert_eq!(keys_with_prefix(&trie, "/a"), ["/a/b/c"]);
+    }
+
+    #[test]
+    fn test_longest_prefix_of() {
+        let trie = make_trie(false, &["/", "/a", "/a/b", "/ab"]);
+        assert_eq!(longest(&trie, ""), None);
+        assert_eq!(longest(&trie, "/"), Some("/"));
+        assert_eq!(longest(&trie, "/a"), Some("/a"));
+        assert_eq!(longest(&trie, "/abc"), Some("/ab"));
+        assert_eq!(longest(&trie, "/a/"), Some("/a"));
+        assert_eq!(longest(&trie, "/a/bc"), Some("/a/b"));
+        assert_eq!(longest(&trie, "/b"), Some("/"));
+        assert_eq!(longest(&trie, "x"), None);
+
+        let trie = make_trie(true, &["", "/", "/a", "/a/b"]);
+        assert_eq!(longest(&trie, "/"), Some("/"));
+        assert_eq!(longest(&trie, "/a"), Some("/a"));
+        assert_eq!(longest(&trie, "/ab"), Some("/"));
+        assert_eq!(longest(&trie, "/a/"), Some("/a"));
+        assert_eq!(longest(&trie, "/a/b/c"), Some("/a/b"));
+        assert_eq!(longest(&trie, "/a/bc"), Some("/a"));
+        assert_eq!(longest(&trie, "x"), Some(""));
+    }
+
+    #[test]
+    fn test_iter_prefix() {
+        let trie = make_trie(false, &["/b", "/a/b", "/ab", "/a", ""]);
+        assert_eq!(
+            trie.iter().map(|(_, &value)| value).collect::<Vec<_>>(),
+            ["", "/a", "/a/b", "/ab", "/b"]
+        );
+        assert_eq!(keys_with_prefix(&trie, "/a"), ["/a", "/a/b", "/ab"]);
+        assert_eq!(keys_with_prefix(&trie, "/a/"), ["/a/b"]);
+        assert_eq!(keys_with_prefix(&trie, "/c"), [] as [&str; 0]);
+
+        let trie = make_trie(true, &["/b", "/a/b", "/ab", "/a", ""]);
+        assert_eq!(keys_with_prefix(&trie, "/a"), ["/a", "/a/b"]);
+        assert_eq!(keys_with_prefix(&trie, "/a/"), ["/a/b"]);
+        assert_eq!(keys_with_prefix(&trie, "").len(), 5);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let mut trie = OsStrTrie::with_component_boundaries();
+        trie.insert(OsStr::from_bytes(b"/caf\xe9"), 1);
+        trie.insert(OsStr::from_bytes(b"/caf\xc3\xa9"), 2);
+
+        assert_eq!(
+            trie.longest_prefix_of(OsStr::from_bytes(b"/caf\xe9/x")),
+            Some((OsStr::from_bytes(b"/caf\xe9"), &1))
+        );
+        assert_eq!(
+            trie.longest_prefix_of(OsStr::new("/caf\u{e9}/x")),
+            Some((OsStr::new("/caf\u{e9}"), &2))
+        );
+        assert_eq!(trie.longest_prefix_of(OsStr::from_bytes(b"/caf\xc3")), None);
+    }
+}
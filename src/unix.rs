@@ -1,252 +1,403 @@
-use std::ffi::{OsStr, OsString};
+use std::borrow::Cow;
+use std::ffi::{CStr, CString, NulError, OsStr, OsString};
 use std::iter::FusedIterator;
 use std::os::unix::ffi::*;
 
-use crate::OsStrExt2;
+use crate::search::{find_bytes, rfind_bytes, CodeUnitSearch};
+use crate::{bytes_to_cstring, CStrExt2, Chunk, NulPolicy, OsStrExt2};
 
-pub struct OsStrFindIter<'a> {
-    haystack: &'a [u8],
-    needle: &'a [u8],
-    left: usize,
-    right: usize,
-}
+pub struct OsStrFindIter<'a>(CodeUnitSearch<'a, u8>);
 
 impl<'a> OsStrFindIter<'a> {
+    #[inline]
     fn new(haystack: &'a [u8], needle: &'a [u8]) -> Self {
-        let (left, right) = if let Some(diff) = haystack.len().checked_sub(needle.len()) {
-            // Add 1 to the right bound; this allow matching on the very last element
-            (0, diff + 1)
-        } else {
-            // Needle is longer than haystack -> force immediate failure
-            (1, 0)
-        };
-
-        Self {
-            haystack,
-            needle,
-            left,
-            right,
-        }
+        Self(CodeUnitSearch::new(haystack, needle))
     }
 }
 
 impl Iterator for OsStrFindIter<'_> {
     type Item = usize;
 
+    #[inline]
     fn next(&mut self) -> Option<usize> {
-        if self.left >= self.right {
-            return None;
-        } else if self.needle.is_empty() {
-            // An empty needle matches the whole way through
-            let index = self.left;
-            self.left += 1;
-            return Some(index);
-        }
+        self.0.next()
+    }
 
-        // Delegate to twoway
-        #[cfg(feature = "twoway")]
-        if let Some(mut index) = twoway::find_bytes(
-            &self.haystack[self.left..self.right + self.needle.len() - 1],
-            self.needle,
-        ) {
-            // Adjust the index
-            index += self.left;
-            // Update for next time
-            self.left = index + 1;
-            return Some(index);
-        }
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
 
-        // Use memchr to find the first character, then make sure that the rest match
-        #[cfg(all(not(feature = "twoway"), feature = "memchr"))]
-        while let Some(mut index) =
-            memchr::memchr(self.needle[0], &self.haystack[self.left..self.right])
-        {
-            // Adjust the index
-            index += self.left;
-            // Update for next time
-            self.left = index + 1;
-
-            if self.needle.len() == 1
-                || self.haystack[index + 1..index + self.needle.len()] == self.needle[1..]
-            {
-                // We found a match!
-                return Some(index);
-            }
-        }
+impl DoubleEndedIterator for OsStrFindIter<'_> {
+    #[inline]
+    fn next_back(&mut self) -> Option<usize> {
+        self.0.next_back()
+    }
+}
 
-        // Naive algorithm
-        #[cfg(not(any(feature = "twoway", feature = "memchr")))]
-        for index in self.left..self.right {
-            if &self.haystack[index..index + self.needle.len()] == self.needle {
-                self.left = index + 1;
-                return Some(index);
-            }
-        }
+impl FusedIterator for OsStrFindIter<'_> {}
 
-        // Force immediate return next time
-        self.left = self.right + 1;
-        None
+impl OsStrExt2 for OsStr {
+    fn starts_with(&self, prefix: &OsStr) -> bool {
+        self.as_bytes().starts_with(prefix.as_bytes())
     }
 
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        (0, Some(self.right.saturating_sub(self.left)))
+    fn ends_with(&self, suffix: &OsStr) -> bool {
+        self.as_bytes().ends_with(suffix.as_bytes())
+    }
+
+    #[inline]
+    fn find_all<'a>(&'a self, needle: &'a OsStr) -> OsStrFindIter<'a> {
+        OsStrFindIter::new(self.as_bytes(), needle.as_bytes())
+    }
+
+    #[inline]
+    fn find(&self, needle: &OsStr) -> Option<usize> {
+        find_bytes(self.as_bytes(), needle.as_bytes())
+    }
+
+    #[inline]
+    fn rfind(&self, needle: &OsStr) -> Option<usize> {
+        rfind_bytes(self.as_bytes(), needle.as_bytes())
+    }
+
+    fn substr(&self, start: usize, end: usize) -> OsString {
+        OsString::from_vec(self.as_bytes()[start..end].into())
+    }
+
+    #[inline]
+    fn split<'a>(&'a self, sep: &'a OsStr) -> OsStrSplit<'a> {
+        OsStrSplit(SplitBytes::new(self.as_bytes(), sep.as_bytes()))
+    }
+
+    fn split_once(&self, sep: &OsStr) -> Option<(&OsStr, &OsStr)> {
+        let bytes = self.as_bytes();
+        let index = find_bytes(bytes, sep.as_bytes())?;
+        Some((
+            OsStr::from_bytes(&bytes[..index]),
+            OsStr::from_bytes(&bytes[index + sep.len()..]),
+        ))
+    }
+
+    #[inline]
+    fn strip_prefix(&self, prefix: &OsStr) -> Option<&OsStr> {
+        self.as_bytes()
+            .strip_prefix(prefix.as_bytes())
+            .map(OsStr::from_bytes)
+    }
+
+    #[inline]
+    fn strip_suffix(&self, suffix: &OsStr) -> Option<&OsStr> {
+        self.as_bytes()
+            .strip_suffix(suffix.as_bytes())
+            .map(OsStr::from_bytes)
+    }
+
+    #[inline]
+    fn to_cstring(&self, policy: NulPolicy) -> Result<CString, NulError> {
+        bytes_to_cstring(self.as_bytes().into(), policy)
     }
 }
 
-impl DoubleEndedIterator for OsStrFindIter<'_> {
-    fn next_back(&mut self) -> Option<usize> {
-        if self.left >= self.right {
-            return None;
-        } else if self.needle.is_empty() {
-            // An empty needle matches the whole way through
-            self.right -= 1;
-            return Some(self.right);
+// Compares a machine word at a time, then finds the first differing byte within the word
+pub(crate) fn common_prefix_bytes(a: &[u8], b: &[u8]) -> usize {
+    const WORD: usize = std::mem::size_of::<usize>();
+
+    let len = a.len().min(b.len());
+    let mut i = 0;
+
+    while i + WORD <= len {
+        let diff = load_word(&a[i..]) ^ load_word(&b[i..]);
+        if diff != 0 {
+            // The first byte in memory order is the lowest one on little-endian targets
+            let bits = if cfg!(target_endian = "little") {
+                diff.trailing_zeros()
+            } else {
+                diff.leading_zeros()
+            };
+            return i + bits as usize / 8;
+        }
+        i += WORD;
+    }
+
+    i + a[i..len]
+        .iter()
+        .zip(&b[i..len])
+        .take_while(|(x, y)| x == y)
+        .count()
+}
+
+pub(crate) fn common_suffix_bytes(a: &[u8], b: &[u8]) -> usize {
+    const WORD: usize = std::mem::size_of::<usize>();
+
+    let len = a.len().min(b.len());
+    let mut n = 0;
+
+    while n + WORD <= len {
+        let diff = load_word(&a[a.len() - n - WORD..]) ^ load_word(&b[b.len() - n - WORD..]);
+        if diff != 0 {
+            // The last byte in memory order is the highest one on little-endian targets
+            let bits = if cfg!(target_endian = "little") {
+                diff.leading_zeros()
+            } else {
+                diff.trailing_zeros()
+            };
+            return n + bits as usize / 8;
         }
+        n += WORD;
+    }
 
-        // Delegate to twoway
-        #[cfg(feature = "twoway")]
-        if let Some(mut index) = twoway::rfind_bytes(
-            &self.haystack[self.left..self.right + self.needle.len() - 1],
-            self.needle,
-        ) {
-            // Adjust the index
-            index += self.left;
+    n + a[..a.len() - n]
+        .iter()
+        .rev()
+        .zip(b[..b.len() - n].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count()
+}
 
-            // Update for next time
-            self.right = index;
+#[inline]
+fn load_word(bytes: &[u8]) -> usize {
+    const WORD: usize = std::mem::size_of::<usize>();
+    let mut buf = [0; WORD];
+    buf.copy_from_slice(&bytes[..WORD]);
+    usize::from_ne_bytes(buf)
+}
 
-            return Some(index);
+pub(crate) struct SplitBytes<'a> {
+    rest: Option<&'a [u8]>,
+    sep: &'a [u8],
+    started: bool,
+}
+
+impl<'a> SplitBytes<'a> {
+    pub(crate) fn new(haystack: &'a [u8], sep: &'a [u8]) -> Self {
+        Self {
+            rest: Some(haystack),
+            sep,
+            started: false,
         }
+    }
+}
 
-        // Use memchr to find the first character, then make sure that the rest match
-        #[cfg(all(not(feature = "twoway"), feature = "memchr"))]
-        while let Some(mut index) =
-            memchr::memrchr(self.needle[0], &self.haystack[self.left..self.right])
-        {
-            // Adjust the index
-            index += self.left;
-
-            // Update for next time
-            self.right = index;
-
-            if self.needle.len() == 1
-                || self.haystack[index + 1..index + self.needle.len()] == self.needle[1..]
-            {
-                // We found a match!
-                return Some(index);
+impl<'a> Iterator for SplitBytes<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        let rest = self.rest?;
+
+        if self.sep.is_empty() {
+            // Like str::split(""): an empty piece at each end, and every unit in between
+            if !self.started {
+                self.started = true;
+                return Some(&rest[..0]);
+            } else if rest.is_empty() {
+                self.rest = None;
+                return Some(rest);
             }
+
+            self.rest = Some(&rest[1..]);
+            return Some(&rest[..1]);
         }
 
-        // Naive algorithm
-        #[cfg(not(any(feature = "twoway", feature = "memchr")))]
-        for index in (self.left..self.right).rev() {
-            if &self.haystack[index..index + self.needle.len()] == self.needle {
-                self.right = index;
-                return Some(index);
+        match find_bytes(rest, self.sep) {
+            Some(index) => {
+                self.rest = Some(&rest[index + self.sep.len()..]);
+                Some(&rest[..index])
+            }
+
+            None => {
+                self.rest = None;
+                Some(rest)
             }
         }
+    }
+}
+
+impl FusedIterator for SplitBytes<'_> {}
+
+pub struct OsStrSplit<'a>(SplitBytes<'a>);
+
+impl<'a> Iterator for OsStrSplit<'a> {
+    type Item = &'a OsStr;
 
-        // Force immediate return next time
-        self.left = self.right + 1;
-        None
+    #[inline]
+    fn next(&mut self) -> Option<&'a OsStr> {
+        self.0.next().map(OsStr::from_bytes)
     }
 }
 
-impl FusedIterator for OsStrFindIter<'_> {}
+impl FusedIterator for OsStrSplit<'_> {}
 
-impl OsStrExt2 for OsStr {
-    fn starts_with(&self, prefix: &OsStr) -> bool {
-        self.as_bytes().starts_with(prefix.as_bytes())
+pub struct CStrSplit<'a>(SplitBytes<'a>);
+
+impl<'a> Iterator for CStrSplit<'a> {
+    type Item = &'a [u8];
+
+    #[inline]
+    fn next(&mut self) -> Option<&'a [u8]> {
+        self.0.next()
     }
+}
 
-    fn ends_with(&self, suffix: &OsStr) -> bool {
-        self.as_bytes().ends_with(suffix.as_bytes())
+impl FusedIterator for CStrSplit<'_> {}
+
+impl CStrExt2 for CStr {
+    fn starts_with(&self, prefix: &CStr) -> bool {
+        self.to_bytes().starts_with(prefix.to_bytes())
+    }
+
+    fn ends_with(&self, suffix: &CStr) -> bool {
+        self.to_bytes().ends_with(suffix.to_bytes())
     }
 
     #[inline]
-    fn find_all<'a>(&'a self, needle: &'a OsStr) -> OsStrFindIter {
-        OsStrFindIter::new(self.as_bytes(), needle.as_bytes())
+    fn find_all<'a>(&'a self, needle: &'a CStr) -> OsStrFindIter<'a> {
+        OsStrFindIter::new(self.to_bytes(), needle.to_bytes())
     }
 
-    #[cfg(feature = "twoway")]
     #[inline]
-    fn find(&self, needle: &OsStr) -> Option<usize> {
-        twoway::find_bytes(self.as_bytes(), needle.as_bytes())
+    fn find(&self, needle: &CStr) -> Option<usize> {
+        find_bytes(self.to_bytes(), needle.to_bytes())
     }
 
-    #[cfg(not(feature = "twoway"))]
-    fn find(&self, needle: &OsStr) -> Option<usize> {
-        let haystack = self.as_bytes();
-        let needle = needle.as_bytes();
-
-        #[cfg(feature = "memchr")]
-        let indices = match needle.len() {
-            0 => return Some(0),
-            1 => return memchr::memchr(needle[0], haystack),
-            len => {
-                memchr::memchr_iter(needle[0], &haystack[..haystack.len().checked_sub(len)? + 1])
-            }
-        };
-
-        #[cfg(not(feature = "memchr"))]
-        let indices = match needle.len() {
-            0 => return Some(0),
-            1 => {
-                let search_ch = needle[0];
-                return haystack.iter().position(|&ch| ch == search_ch);
-            }
-            len => (0..=(haystack.len().checked_sub(len)?)),
-        };
+    #[inline]
+    fn rfind(&self, needle: &CStr) -> Option<usize> {
+        rfind_bytes(self.to_bytes(), needle.to_bytes())
+    }
 
-        for i in indices {
-            if &haystack[i..i + needle.len()] == needle {
-                return Some(i);
-            }
+    #[inline]
+    fn split<'a>(&'a self, sep: &'a CStr) -> CStrSplit<'a> {
+        CStrSplit(SplitBytes::new(self.to_bytes(), sep.to_bytes()))
+    }
+
+    fn split_once(&self, sep: &CStr) -> Option<(&[u8], &CStr)> {
+        let index = self.find(sep)?;
+        Some((
+            &self.to_bytes()[..index],
+            self.substr_from(index + sep.to_bytes().len()),
+        ))
+    }
+
+    fn rsplit_once(&self, sep: &CStr) -> Option<(&[u8], &CStr)> {
+        let index = self.rfind(sep)?;
+        Some((
+            &self.to_bytes()[..index],
+            self.substr_from(index + sep.to_bytes().len()),
+        ))
+    }
+
+    fn strip_prefix(&self, prefix: &CStr) -> Option<&CStr> {
+        if self.starts_with(prefix) {
+            Some(self.substr_from(prefix.to_bytes().len()))
+        } else {
+            None
         }
+    }
+
+    fn strip_suffix(&self, suffix: &CStr) -> Option<&[u8]> {
+        self.to_bytes().strip_suffix(suffix.to_bytes())
+    }
 
-        None
+    fn substr_from(&self, start: usize) -> &CStr {
+        let bytes = &self.to_bytes_with_nul()[start..];
+        // Make sure we didn't cut off the NUL terminator
+        assert!(!bytes.is_empty(), "start index out of range");
+        // This is a suffix of a valid C string, so it's also a valid C string
+        unsafe { CStr::from_bytes_with_nul_unchecked(bytes) }
     }
 
-    #[cfg(feature = "twoway")]
     #[inline]
-    fn rfind(&self, needle: &OsStr) -> Option<usize> {
-        twoway::rfind_bytes(self.as_bytes(), needle.as_bytes())
+    fn to_os_str(&self) -> &OsStr {
+        OsStr::from_bytes(self.to_bytes())
     }
+}
 
-    #[cfg(not(feature = "twoway"))]
-    fn rfind(&self, needle: &OsStr) -> Option<usize> {
-        let haystack = self.as_bytes();
-        let needle = needle.as_bytes();
-
-        #[cfg(feature = "memchr")]
-        let indices = match needle.len() {
-            0 => return Some(haystack.len()),
-            1 => return memchr::memrchr(needle[0], haystack),
-            len => {
-                memchr::memrchr_iter(needle[0], &haystack[..haystack.len().checked_sub(len)? + 1])
-            }
-        };
-
-        #[cfg(not(feature = "memchr"))]
-        let indices = match needle.len() {
-            0 => return Some(haystack.len()),
-            1 => {
-                let search_ch = needle[0];
-                return haystack.iter().rposition(|&ch| ch == search_ch);
+pub(crate) type CodeUnit = u8;
+
+pub(crate) fn for_each_chunk<F: FnMut(Chunk)>(s: &OsStr, mut f: F) {
+    let mut bytes = s.as_bytes();
+
+    while !bytes.is_empty() {
+        match std::str::from_utf8(bytes) {
+            Ok(valid) => {
+                f(Chunk::Valid(valid));
+                return;
             }
-            len => (0..=(haystack.len().checked_sub(len)?)).rev(),
-        };
 
-        for i in indices {
-            if &haystack[i..i + needle.len()] == needle {
-                return Some(i);
+            Err(e) => {
+                let (valid, rest) = bytes.split_at(e.valid_up_to());
+                if !valid.is_empty() {
+                    // The error told us that everything up to here is valid UTF-8
+                    f(Chunk::Valid(unsafe {
+                        std::str::from_utf8_unchecked(valid)
+                    }));
+                }
+
+                // If there's no error length, the string ends with a truncated sequence
+                let invalid_len = e.error_len().unwrap_or(rest.len());
+                f(Chunk::Invalid(&rest[..invalid_len]));
+                bytes = &rest[invalid_len..];
             }
         }
-
-        None
     }
+}
 
-    fn substr(&self, start: usize, end: usize) -> OsString {
-        OsString::from_vec(self.as_bytes()[start..end].into())
-    }
+// The platform's internal representation (on Windows, this is WTF-8)
+#[inline]
+pub(crate) fn encoded_bytes(s: &OsStr) -> &[u8] {
+    s.as_bytes()
+}
+
+// `bytes` must have come from slicing the result of `encoded_bytes()` at code point boundaries
+#[inline]
+pub(crate) unsafe fn from_encoded_bytes(bytes: &[u8]) -> &OsStr {
+    OsStr::from_bytes(bytes)
+}
+
+#[inline]
+pub(crate) fn unit_len(s: &OsStr) -> usize {
+    s.len()
+}
+
+#[inline]
+pub(crate) fn char_unit_len(ch: char) -> usize {
+    ch.len_utf8()
+}
+
+#[inline]
+pub(crate) fn code_units(s: &OsStr) -> Cow<'_, [CodeUnit]> {
+    Cow::Borrowed(s.as_bytes())
+}
+
+#[inline]
+pub(crate) fn unit_to_ascii(unit: CodeUnit) -> Option<u8> {
+    Some(unit).filter(u8::is_ascii)
+}
+
+// Escapes a unit that isn't part of valid Unicode
+pub(crate) fn push_escape(out: &mut String, unit: CodeUnit) {
+    use std::fmt::Write;
+    write!(out, "\\x{:02x}", unit).unwrap();
+}
+
+#[inline]
+pub(crate) fn push_str_units(units: &mut Vec<CodeUnit>, s: &str) {
+    units.extend_from_slice(s.as_bytes());
+}
+
+#[inline]
+pub(crate) fn from_code_units(units: Vec<CodeUnit>) -> OsString {
+    OsString::from_vec(units)
+}
+
+#[inline]
+pub(crate) fn to_wtf8(s: &OsStr) -> Cow<'_, [u8]> {
+    Cow::Borrowed(s.as_bytes())
+}
+
+#[inline]
+#[cfg(feature = "serde")]
+pub(crate) fn from_wtf8(bytes: Vec<u8>) -> Option<OsString> {
+    Some(OsString::from_vec(bytes))
 }
@@ -0,0 +1,159 @@
+//! Converting between `OsStr`s and (potentially ill-formed) UTF-16, and searching UTF-16 buffers.
+//!
+//! On Unix, unpaired surrogates can be stored in an `OsString` using WTF-8 (see
+//! [`UnpairedPolicy::Wtf8`]); [`to_utf16()`] understands that encoding, so the round trip is
+//! lossless.
+
+use std::error::Error;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+
+use crate::search;
+use crate::wtf8::{UnpairedPolicy, UnpairedSurrogateError, Wtf8Buf};
+
+#[cfg(unix)]
+use crate::wtf8::{check_wtf8, Wtf8};
+
+/// The error returned by [`to_utf16()`] if the string isn't valid WTF-8.
+///
+/// This never happens on Windows.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ToUtf16Error {
+    valid_up_to: usize,
+}
+
+impl ToUtf16Error {
+    /// The length of the prefix that could have been converted, in bytes.
+    #[inline]
+    pub fn valid_up_to(&self) -> usize {
+        self.valid_up_to
+    }
+}
+
+impl fmt::Display for ToUtf16Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid WTF-8 sequence at offset {}", self.valid_up_to)
+    }
+}
+
+impl Error for ToUtf16Error {}
+
+/// Decode potentially ill-formed UTF-16 into an `OsString`, handling unpaired surrogates according
+/// to `policy`.
+///
+/// Only [`UnpairedPolicy::Error`] can fail.
+#[inline]
+pub fn from_utf16_lossless(
+    units: &[u16],
+    policy: UnpairedPolicy,
+) -> Result<OsString, UnpairedSurrogateError> {
+    Wtf8Buf::from_wide(units).into_os_string(policy)
+}
+
+/// Encode `s` as UTF-16.
+///
+/// On Unix, `s` must be valid UTF-8, or WTF-8 with encoded surrogates (which are converted back to
+/// unpaired surrogates). On Windows, this always succeeds.
+pub fn to_utf16(s: &OsStr) -> Result<Vec<u16>, ToUtf16Error> {
+    #[cfg(windows)]
+    return Ok(std::os::windows::ffi::OsStrExt::encode_wide(s).collect());
+
+    #[cfg(unix)]
+    match Wtf8::from_os_str(s) {
+        Some(s) => Ok(s.encode_wide().collect()),
+        None => Err(ToUtf16Error {
+            valid_up_to: check_wtf8(crate::encoded_bytes(s)).unwrap_err(),
+        }),
+    }
+}
+
+/// Find the first occurrence of `needle` in a UTF-16 buffer.
+///
+/// The result is in UTF-16 code units, so it's the same as what
+/// [`OsStrExt2::find()`](crate::OsStrExt2::find) would return on Windows for the equivalent
+/// `OsStr`. Returns `None` if `needle` can't be converted with [`to_utf16()`].
+pub fn find_in_wide(haystack: &[u16], needle: &OsStr) -> Option<usize> {
+    let needle = to_utf16(needle).ok()?;
+    search::find(haystack, &needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wide(s: &str) -> Vec<u16> {
+        s.encode_utf16().collect()
+    }
+
+    #[test]
+    fn test_from_utf16_lossless() {
+        let units = [0x61, 0xD800, 0x62];
+
+        assert_eq!(
+            from_utf16_lossless(&units, UnpairedPolicy::Replace).unwrap(),
+            "a\u{fffd}b"
+        );
+        assert_eq!(
+            from_utf16_lossless(&units, UnpairedPolicy::Error)
+                .unwrap_err()
+                .index(),
+            1
+        );
+
+        let s = from_utf16_lossless(&units, UnpairedPolicy::Wtf8).unwrap();
+        assert_eq!(to_utf16(&s).unwrap(), units);
+
+        for &policy in [
+            UnpairedPolicy::Replace,
+            UnpairedPolicy::Wtf8,
+            UnpairedPolicy::Error,
+        ]
+        .iter()
+        {
+            let s = from_utf16_lossless(&wide("caf\u{e9} \u{1f600}"), policy).unwrap();
+            assert_eq!(s, "caf\u{e9} \u{1f600}");
+        }
+    }
+
+    #[test]
+    fn test_to_utf16() {
+        assert_eq!(to_utf16(OsStr::new("")).unwrap(), [] as [u16; 0]);
+        assert_eq!(
+            to_utf16(OsStr::new("a\u{1f600}")).unwrap(),
+            [0x61, 0xD83D, 0xDE00]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_to_utf16_invalid() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let err = to_utf16(OsStr::from_bytes(b"ab\xffc")).unwrap_err();
+        assert_eq!(err.valid_up_to(), 2);
+        assert_eq!(err.to_string(), "invalid WTF-8 sequence at offset 2");
+
+        // An encoded surrogate pair should have been a single code point
+        let err = to_utf16(OsStr::from_bytes(b"\xed\xa0\x80\xed\xa0\xbd\xed\xb8\x80")).unwrap_err();
+        assert_eq!(err.valid_up_to(), 3);
+    }
+
+    #[test]
+    fn test_find_in_wide() {
+        let haystack = wide("\u{1f600}abc\u{e9}abc");
+
+        assert_eq!(find_in_wide(&haystack, OsStr::new("abc")), Some(2));
+        assert_eq!(find_in_wide(&haystack, OsStr::new("\u{e9}")), Some(5));
+        assert_eq!(find_in_wide(&haystack, OsStr::new("")), Some(0));
+        assert_eq!(find_in_wide(&haystack, OsStr::new("x")), None);
+
+        let s = from_utf16_lossless(&[0xD83D], UnpairedPolicy::Wtf8).unwrap();
+        assert_eq!(find_in_wide(&haystack, &s), Some(0));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            assert_eq!(find_in_wide(&haystack, OsStr::from_bytes(b"\xff")), None);
+        }
+    }
+}
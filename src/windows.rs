@@ -1,91 +1,140 @@
-use std::collections::VecDeque;
-use std::ffi::{OsStr, OsString};
+use std::borrow::Cow;
+use std::ffi::{CString, NulError, OsStr, OsString};
 use std::iter::FusedIterator;
+use std::marker::PhantomData;
 use std::os::windows::ffi::*;
 
-use crate::OsStrExt2;
+use crate::search::{self, find_bytes, SearchState};
+use crate::{bytes_to_cstring, Chunk, NulPolicy, OsStrExt2};
 
-pub struct OsStrFindIter {
+pub struct OsStrFindIter<'a> {
     haystack: Vec<u16>,
     needle: Vec<u16>,
-    left: usize,
-    right: usize,
+    state: SearchState,
+    _phantom: PhantomData<&'a OsStr>,
 }
 
-impl OsStrFindIter {
+impl OsStrFindIter<'_> {
     fn new(haystack: EncodeWide, needle: EncodeWide) -> Self {
         let haystack: Vec<u16> = haystack.collect();
         let needle: Vec<u16> = needle.collect();
 
-        let (left, right) = if let Some(diff) = haystack.len().checked_sub(needle.len()) {
-            // Add 1 to the right bound; this allow matching on the very last element
-            (0, diff + 1)
-        } else {
-            // Needle is longer than haystack -> force immediate failure
-            (1, 0)
-        };
-
         Self {
-            haystack: haystack,
-            needle: needle,
-            left,
-            right,
+            state: SearchState::new(haystack.len(), needle.len()),
+            haystack,
+            needle,
+            _phantom: PhantomData,
         }
     }
 }
 
-impl Iterator for OsStrFindIter {
+impl Iterator for OsStrFindIter<'_> {
     type Item = usize;
 
+    #[inline]
     fn next(&mut self) -> Option<usize> {
-        if self.left >= self.right {
-            return None;
-        } else if self.needle.is_empty() {
-            // An empty needle matches the whole way through
-            let index = self.left;
-            self.left += 1;
-            return Some(index);
-        }
-
-        // Naive search, but it works
-        for index in self.left..self.right {
-            if &self.haystack[index..index + self.needle.len()] == self.needle {
-                self.left = index + 1;
-                return Some(index);
-            }
-        }
+        self.state.next(&self.haystack, &self.needle)
+    }
 
-        // Force immediate return next time
-        self.left = self.right + 1;
-        None
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.state.size_hint()
     }
 }
 
-impl DoubleEndedIterator for OsStrFindIter {
+impl DoubleEndedIterator for OsStrFindIter<'_> {
+    #[inline]
     fn next_back(&mut self) -> Option<usize> {
-        if self.left >= self.right {
-            return None;
-        } else if self.needle.is_empty() {
-            // An empty needle matches the whole way through
-            self.right -= 1;
-            return Some(self.right);
+        self.state.next_back(&self.haystack, &self.needle)
+    }
+}
+
+impl FusedIterator for OsStrFindIter<'_> {}
+
+// Both of these work on WTF-8 and back up to a code point boundary, so the results can be used
+// to slice the encoded bytes
+pub(crate) fn common_prefix_bytes(a: &[u8], b: &[u8]) -> usize {
+    let mut n = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    while n > 0 && (is_continuation(a.get(n)) || is_continuation(b.get(n))) {
+        n -= 1;
+    }
+    n
+}
+
+pub(crate) fn common_suffix_bytes(a: &[u8], b: &[u8]) -> usize {
+    let mut n = a
+        .iter()
+        .rev()
+        .zip(b.iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    while n > 0 && is_continuation(a.get(a.len() - n)) {
+        n -= 1;
+    }
+    n
+}
+
+#[inline]
+fn is_continuation(byte: Option<&u8>) -> bool {
+    matches!(byte, Some(&b) if b & 0xc0 == 0x80)
+}
+
+// Works on the WTF-8 representation, so the pieces can be borrowed. A separator can never match
+// in the middle of a code point (or in the middle of a surrogate pair), so every piece is still
+// valid WTF-8. This is also why the results can differ from find(), which works on UTF-16 (see
+// the docs for OsStrExt2::split()).
+pub struct OsStrSplit<'a> {
+    rest: Option<&'a [u8]>,
+    sep: &'a [u8],
+    started: bool,
+}
+
+impl<'a> OsStrSplit<'a> {
+    fn new(haystack: &'a [u8], sep: &'a [u8]) -> Self {
+        Self {
+            rest: Some(haystack),
+            sep,
+            started: false,
         }
+    }
+}
 
-        // Naive search, but it works
-        for index in (self.left..self.right).rev() {
-            if &self.haystack[index..index + self.needle.len()] == self.needle {
-                self.right = index;
-                return Some(index);
+impl<'a> Iterator for OsStrSplit<'a> {
+    type Item = &'a OsStr;
+
+    fn next(&mut self) -> Option<&'a OsStr> {
+        let rest = self.rest?;
+
+        let (piece, rest) = if self.sep.is_empty() {
+            // Like str::split(""): an empty piece at each end, and every code point in between
+            if !self.started {
+                self.started = true;
+                (&rest[..0], Some(rest))
+            } else if rest.is_empty() {
+                (rest, None)
+            } else {
+                let len = match rest[0] {
+                    0x00..=0x7F => 1,
+                    0x80..=0xDF => 2,
+                    0xE0..=0xEF => 3,
+                    _ => 4,
+                };
+                (&rest[..len], Some(&rest[len..]))
             }
-        }
+        } else {
+            match find_bytes(rest, self.sep) {
+                Some(index) => (&rest[..index], Some(&rest[index + self.sep.len()..])),
+                None => (rest, None),
+            }
+        };
 
-        // Force immediate return next time
-        self.left = self.right + 1;
-        None
+        self.rest = rest;
+        // See above for why this is still valid WTF-8
+        Some(unsafe { OsStr::from_encoded_bytes_unchecked(piece) })
     }
 }
 
-impl FusedIterator for OsStrFindIter {}
+impl FusedIterator for OsStrSplit<'_> {}
 
 impl OsStrExt2 for OsStr {
     fn starts_with(&self, prefix: &OsStr) -> bool {
@@ -121,98 +170,204 @@ impl OsStrExt2 for OsStr {
     }
 
     fn ends_with(&self, suffix: &OsStr) -> bool {
-        if suffix.is_empty() {
-            return true;
-        } else if self.is_empty() {
-            return false;
+        // encode_wide() can't be reversed, so skip ahead instead
+        let len = self.encode_wide().count();
+        match len.checked_sub(suffix.encode_wide().count()) {
+            Some(start) => self.encode_wide().skip(start).eq(suffix.encode_wide()),
+            None => false,
         }
+    }
 
-        // Collect the unicode points in the suffix
-        let suffix_seq: Vec<u16> = suffix.encode_wide().collect();
+    // Searching needs slices (for memchr), so these have to collect the units first
 
-        // Now collect the unicode points in self, but only keep the last
-        // <however many the suffix has>.
-        let mut self_seq = VecDeque::with_capacity(suffix_seq.len());
-        for self_ch in self.encode_wide() {
-            if self_seq.len() >= suffix_seq.len() {
-                self_seq.pop_front();
-            }
-            self_seq.push_back(self_ch);
-        }
+    fn find(&self, needle: &OsStr) -> Option<usize> {
+        let units: Vec<u16> = self.encode_wide().collect();
+        let needle: Vec<u16> = needle.encode_wide().collect();
+        search::find(&units, &needle)
+    }
 
-        // Compare the two
-        self_seq == suffix_seq
+    fn rfind(&self, needle: &OsStr) -> Option<usize> {
+        let units: Vec<u16> = self.encode_wide().collect();
+        let needle: Vec<u16> = needle.encode_wide().collect();
+        search::rfind(&units, &needle)
     }
 
-    fn find(&self, needle: &OsStr) -> Option<usize> {
-        if needle.is_empty() {
-            return Some(0);
-        } else if self.is_empty() {
-            return None;
+    fn find_all<'a>(&'a self, needle: &'a OsStr) -> OsStrFindIter<'a> {
+        OsStrFindIter::new(self.encode_wide(), needle.encode_wide())
+    }
+
+    fn substr(&self, start: usize, end: usize) -> OsString {
+        OsString::from_wide(
+            &self
+                .encode_wide()
+                .skip(start)
+                .take(end - start)
+                .collect::<Vec<u16>>(),
+        )
+    }
+
+    #[inline]
+    fn split<'a>(&'a self, sep: &'a OsStr) -> OsStrSplit<'a> {
+        OsStrSplit::new(self.as_encoded_bytes(), sep.as_encoded_bytes())
+    }
+
+    // These all work on the WTF-8 representation; see OsStrSplit for why that's safe
+
+    fn split_once(&self, sep: &OsStr) -> Option<(&OsStr, &OsStr)> {
+        let bytes = self.as_encoded_bytes();
+        let sep = sep.as_encoded_bytes();
+        let index = find_bytes(bytes, sep)?;
+        unsafe {
+            Some((
+                OsStr::from_encoded_bytes_unchecked(&bytes[..index]),
+                OsStr::from_encoded_bytes_unchecked(&bytes[index + sep.len()..]),
+            ))
         }
+    }
 
-        // Collect the unicode points in the search string
-        let needle: Vec<u16> = needle.encode_wide().collect();
+    #[inline]
+    fn strip_prefix(&self, prefix: &OsStr) -> Option<&OsStr> {
+        self.as_encoded_bytes()
+            .strip_prefix(prefix.as_encoded_bytes())
+            .map(|bytes| unsafe { OsStr::from_encoded_bytes_unchecked(bytes) })
+    }
 
-        let mut haystack_q = VecDeque::with_capacity(needle.len());
-        for (i, self_ch) in self.encode_wide().enumerate() {
-            // Collect the unicode points in self, but only keep the last
-            // <however many the search string has>.
-            if haystack_q.len() >= needle.len() {
-                haystack_q.pop_front();
-            }
-            haystack_q.push_back(self_ch);
+    #[inline]
+    fn strip_suffix(&self, suffix: &OsStr) -> Option<&OsStr> {
+        self.as_encoded_bytes()
+            .strip_suffix(suffix.as_encoded_bytes())
+            .map(|bytes| unsafe { OsStr::from_encoded_bytes_unchecked(bytes) })
+    }
 
-            if haystack_q == needle {
-                // Found a match; return it
-                return Some(i + 1 - haystack_q.len());
+    #[inline]
+    fn to_cstring(&self, policy: NulPolicy) -> Result<CString, NulError> {
+        bytes_to_cstring(to_wtf8(self).into_owned(), policy)
+    }
+}
+
+pub(crate) type CodeUnit = u16;
+
+pub(crate) fn for_each_chunk<F: FnMut(Chunk)>(s: &OsStr, mut f: F) {
+    let mut buf = String::new();
+
+    for res in std::char::decode_utf16(s.encode_wide()) {
+        match res {
+            Ok(ch) => buf.push(ch),
+
+            Err(e) => {
+                if !buf.is_empty() {
+                    f(Chunk::Valid(&buf));
+                    buf.clear();
+                }
+                f(Chunk::Invalid(&[e.unpaired_surrogate()]));
             }
         }
+    }
 
+    if !buf.is_empty() {
+        f(Chunk::Valid(&buf));
+    }
+}
+
+// The platform's internal representation (on Windows, this is WTF-8)
+#[inline]
+pub(crate) fn encoded_bytes(s: &OsStr) -> &[u8] {
+    s.as_encoded_bytes()
+}
+
+// `bytes` must have come from slicing the result of `encoded_bytes()` at code point boundaries
+#[inline]
+pub(crate) unsafe fn from_encoded_bytes(bytes: &[u8]) -> &OsStr {
+    OsStr::from_encoded_bytes_unchecked(bytes)
+}
+
+#[inline]
+pub(crate) fn unit_len(s: &OsStr) -> usize {
+    s.encode_wide().count()
+}
+
+#[inline]
+pub(crate) fn char_unit_len(ch: char) -> usize {
+    ch.len_utf16()
+}
+
+#[inline]
+pub(crate) fn code_units(s: &OsStr) -> Cow<'_, [CodeUnit]> {
+    Cow::Owned(s.encode_wide().collect())
+}
+
+#[inline]
+pub(crate) fn unit_to_ascii(unit: CodeUnit) -> Option<u8> {
+    if unit < 0x80 {
+        Some(unit as u8)
+    } else {
         None
     }
+}
 
-    fn rfind(&self, needle: &OsStr) -> Option<usize> {
-        if needle.is_empty() {
-            return Some(self.encode_wide().count());
-        } else if self.is_empty() {
-            return None;
-        }
+// Escapes a unit that isn't part of valid Unicode
+pub(crate) fn push_escape(out: &mut String, unit: CodeUnit) {
+    use std::fmt::Write;
+    write!(out, "\\u{:04x}", unit).unwrap();
+}
+
+#[inline]
+pub(crate) fn push_str_units(units: &mut Vec<CodeUnit>, s: &str) {
+    units.extend(s.encode_utf16());
+}
 
-        let mut res = None;
+#[inline]
+pub(crate) fn from_code_units(units: Vec<CodeUnit>) -> OsString {
+    OsString::from_wide(&units)
+}
 
-        // Collect the unicode points in the search string
-        let needle: Vec<u16> = needle.encode_wide().collect();
+pub(crate) fn to_wtf8(s: &OsStr) -> Cow<'_, [u8]> {
+    let mut bytes = Vec::new();
 
-        let mut haystack_q = VecDeque::with_capacity(needle.len());
-        for (i, self_ch) in self.encode_wide().enumerate() {
-            // Collect the unicode points in self, but only keep the last
-            // <however many the search string has>.
-            if haystack_q.len() >= needle.len() {
-                haystack_q.pop_front();
-            }
-            haystack_q.push_back(self_ch);
+    for res in std::char::decode_utf16(s.encode_wide()) {
+        match res {
+            Ok(ch) => bytes.extend_from_slice(ch.encode_utf8(&mut [0; 4]).as_bytes()),
 
-            if haystack_q == needle {
-                // Found a match; store it until the end
-                res = Some(i + 1 - haystack_q.len());
+            Err(e) => {
+                // Lone surrogates get the "generalized UTF-8" 3-byte encoding
+                let unit = e.unpaired_surrogate();
+                bytes.push(0xE0 | (unit >> 12) as u8);
+                bytes.push(0x80 | ((unit >> 6) & 0x3F) as u8);
+                bytes.push(0x80 | (unit & 0x3F) as u8);
             }
         }
-
-        res
     }
 
-    fn find_all(&self, needle: &OsStr) -> OsStrFindIter {
-        OsStrFindIter::new(self.encode_wide(), needle.encode_wide())
-    }
+    Cow::Owned(bytes)
+}
 
-    fn substr(&self, start: usize, end: usize) -> OsString {
-        OsString::from_wide(
-            &self
-                .encode_wide()
-                .skip(start)
-                .take(end - start)
-                .collect::<Vec<u16>>(),
-        )
+#[cfg(feature = "serde")]
+pub(crate) fn from_wtf8(bytes: Vec<u8>) -> Option<OsString> {
+    let mut units = Vec::with_capacity(bytes.len());
+    let mut bytes = &bytes[..];
+
+    while !bytes.is_empty() {
+        match std::str::from_utf8(bytes) {
+            Ok(valid) => {
+                units.extend(valid.encode_utf16());
+                break;
+            }
+
+            Err(e) => {
+                let (valid, rest) = bytes.split_at(e.valid_up_to());
+                units.extend(unsafe { std::str::from_utf8_unchecked(valid) }.encode_utf16());
+
+                // The only thing WTF-8 allows that UTF-8 doesn't is an encoded surrogate
+                match rest {
+                    [0xED, b1 @ 0xA0..=0xBF, b2 @ 0x80..=0xBF, ..] => {
+                        units.push(0xD000 | ((*b1 as u16 & 0x3F) << 6) | (*b2 as u16 & 0x3F));
+                        bytes = &rest[3..];
+                    }
+                    _ => return None,
+                }
+            }
+        }
     }
+
+    Some(OsString::from_wide(&units))
 }
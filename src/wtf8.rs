@@ -0,0 +1,868 @@
+//! WTF-8 strings, for handling Windows file names (which can contain unpaired surrogates) on any
+//! platform.
+//!
+//! Offsets in the search/slice methods are in UTF-16 code units, the same as
+//! [`OsStrExt2`](crate::OsStrExt2) on Windows.
+
+use std::borrow::{Borrow, Cow};
+use std::error::Error;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::iter::FusedIterator;
+use std::ops::Deref;
+
+use crate::find_bytes;
+use crate::search::SearchState;
+
+/// What to do with unpaired surrogates when converting to something that might not be able to
+/// hold them.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum UnpairedPolicy {
+    /// Replace each of them with U+FFFD.
+    Replace,
+    /// Keep their WTF-8 encoding. This is lossless, but the result is not valid UTF-8.
+    Wtf8,
+    /// Fail with an [`UnpairedSurrogateError`].
+    Error,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct UnpairedSurrogateError {
+    unit: u16,
+    index: usize,
+}
+
+impl UnpairedSurrogateError {
+    #[inline]
+    pub fn unpaired_surrogate(&self) -> u16 {
+        self.unit
+    }
+
+    /// The offset of the surrogate, in UTF-16 code units.
+    #[inline]
+    pub fn index(&self) -> usize {
+        self.index
+    }
+}
+
+impl fmt::Display for UnpairedSurrogateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "unpaired surrogate {:#06x} at index {}",
+            self.unit, self.index
+        )
+    }
+}
+
+impl Error for UnpairedSurrogateError {}
+
+/// A borrowed WTF-8 string.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(transparent)]
+pub struct Wtf8 {
+    bytes: [u8],
+}
+
+impl Wtf8 {
+    #[inline]
+    pub fn new<S: AsRef<str> + ?Sized>(s: &S) -> &Self {
+        unsafe { Self::from_bytes_unchecked(s.as_ref().as_bytes()) }
+    }
+
+    /// Check that `bytes` is valid WTF-8.
+    ///
+    /// This is UTF-8, except that surrogates may also be encoded, as long as they aren't a high
+    /// surrogate followed by a low surrogate (those must be encoded as a single code point).
+    #[inline]
+    pub fn from_bytes(bytes: &[u8]) -> Option<&Self> {
+        if is_valid(bytes) {
+            Some(unsafe { Self::from_bytes_unchecked(bytes) })
+        } else {
+            None
+        }
+    }
+
+    /// Borrow an `OsStr` as WTF-8.
+    ///
+    /// This always succeeds on Windows. On Unix, `s` has to be valid WTF-8 (for example, the
+    /// result of [`Wtf8::to_os_string()`] with [`UnpairedPolicy::Wtf8`]).
+    #[inline]
+    pub fn from_os_str(s: &OsStr) -> Option<&Self> {
+        Self::from_bytes(crate::encoded_bytes(s))
+    }
+
+    #[inline]
+    unsafe fn from_bytes_unchecked(bytes: &[u8]) -> &Self {
+        &*(bytes as *const [u8] as *const Self)
+    }
+
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// The length in bytes.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Returns `None` if there are any unpaired surrogates.
+    #[inline]
+    pub fn as_str(&self) -> Option<&str> {
+        std::str::from_utf8(&self.bytes).ok()
+    }
+
+    pub fn to_string_lossy(&self) -> Cow<'_, str> {
+        if let Some(s) = self.as_str() {
+            return Cow::Borrowed(s);
+        }
+
+        let mut res = String::with_capacity(self.bytes.len());
+        for chunk in self.chunks() {
+            match chunk {
+                Ok(s) => res.push_str(s),
+                Err(_) => res.push(char::REPLACEMENT_CHARACTER),
+            }
+        }
+        Cow::Owned(res)
+    }
+
+    #[inline]
+    pub fn encode_wide(&self) -> EncodeWide<'_> {
+        EncodeWide {
+            bytes: self.bytes.iter(),
+            low: None,
+        }
+    }
+
+    /// Convert to an `OsString`, handling unpaired surrogates according to `policy`.
+    ///
+    /// With [`UnpairedPolicy::Wtf8`], this never fails, and the result can be turned back into a
+    /// `Wtf8` with [`Wtf8::from_os_str()`].
+    pub fn to_os_string(&self, policy: UnpairedPolicy) -> Result<OsString, UnpairedSurrogateError> {
+        match policy {
+            UnpairedPolicy::Replace => Ok(self.to_string_lossy().into_owned().into()),
+
+            UnpairedPolicy::Error => match self.as_str() {
+                Some(s) => Ok(s.into()),
+                None => Err(self.first_unpaired().unwrap()),
+            },
+
+            UnpairedPolicy::Wtf8 => {
+                #[cfg(unix)]
+                return Ok(std::os::unix::ffi::OsStringExt::from_vec(
+                    self.bytes.to_vec(),
+                ));
+
+                #[cfg(windows)]
+                return Ok(std::os::windows::ffi::OsStringExt::from_wide(
+                    &self.encode_wide().collect::<Vec<_>>(),
+                ));
+            }
+        }
+    }
+
+    fn first_unpaired(&self) -> Option<UnpairedSurrogateError> {
+        let mut index = 0;
+        for chunk in self.chunks() {
+            match chunk {
+                Ok(s) => index += s.encode_utf16().count(),
+                Err(unit) => return Some(UnpairedSurrogateError { unit, index }),
+            }
+        }
+        None
+    }
+
+    #[inline]
+    fn chunks(&self) -> Chunks<'_> {
+        Chunks { bytes: &self.bytes }
+    }
+
+    fn initial_low_surrogate(&self) -> Option<u16> {
+        match self.bytes {
+            [0xED, b1 @ 0xB0..=0xBF, b2, ..] => Some(decode_surrogate(b1, b2)),
+            _ => None,
+        }
+    }
+
+    fn final_high_surrogate(&self) -> Option<u16> {
+        match self.bytes {
+            [.., 0xED, b1 @ 0xA0..=0xAF, b2] => Some(decode_surrogate(b1, b2)),
+            _ => None,
+        }
+    }
+
+    // These mirror the `OsStrExt2` methods on Windows. Anything that returns offsets works on
+    // UTF-16 code units (so, for example, a lone high surrogate can match the first half of a
+    // pair); splitting and stripping work on the WTF-8 bytes so the results can be borrowed.
+
+    pub fn starts_with(&self, prefix: &Wtf8) -> bool {
+        let mut units = self.encode_wide();
+        prefix.encode_wide().all(|unit| units.next() == Some(unit))
+    }
+
+    pub fn ends_with(&self, suffix: &Wtf8) -> bool {
+        let units: Vec<u16> = self.encode_wide().collect();
+        let suffix: Vec<u16> = suffix.encode_wide().collect();
+        units.ends_with(&suffix)
+    }
+
+    #[inline]
+    pub fn find(&self, needle: &Wtf8) -> Option<usize> {
+        self.find_all(needle).next()
+    }
+
+    #[inline]
+    pub fn rfind(&self, needle: &Wtf8) -> Option<usize> {
+        self.find_all(needle).next_back()
+    }
+
+    #[inline]
+    pub fn find_all(&self, needle: &Wtf8) -> Wtf8FindIter {
+        Wtf8FindIter::new(self.encode_wide().collect(), needle.encode_wide().collect())
+    }
+
+    /// Get the code units from `start` to `end`. Slicing in the middle of a surrogate pair leaves
+    /// an unpaired surrogate.
+    pub fn substr(&self, start: usize, end: usize) -> Wtf8Buf {
+        let units: Vec<u16> = self.encode_wide().skip(start).take(end - start).collect();
+        Wtf8Buf::from_wide(&units)
+    }
+
+    #[inline]
+    pub fn split<'a>(&'a self, sep: &'a Wtf8) -> Wtf8Split<'a> {
+        Wtf8Split {
+            rest: Some(&self.bytes),
+            sep: &sep.bytes,
+            started: false,
+        }
+    }
+
+    pub fn split_once(&self, sep: &Wtf8) -> Option<(&Wtf8, &Wtf8)> {
+        let index = find_bytes(&self.bytes, &sep.bytes)?;
+        unsafe {
+            Some((
+                Self::from_bytes_unchecked(&self.bytes[..index]),
+                Self::from_bytes_unchecked(&self.bytes[index + sep.bytes.len()..]),
+            ))
+        }
+    }
+
+    #[inline]
+    pub fn strip_prefix(&self, prefix: &Wtf8) -> Option<&Wtf8> {
+        self.bytes
+            .strip_prefix(&prefix.bytes)
+            .map(|bytes| unsafe { Self::from_bytes_unchecked(bytes) })
+    }
+
+    #[inline]
+    pub fn strip_suffix(&self, suffix: &Wtf8) -> Option<&Wtf8> {
+        self.bytes
+            .strip_suffix(&suffix.bytes)
+            .map(|bytes| unsafe { Self::from_bytes_unchecked(bytes) })
+    }
+}
+
+impl ToOwned for Wtf8 {
+    type Owned = Wtf8Buf;
+
+    #[inline]
+    fn to_owned(&self) -> Wtf8Buf {
+        Wtf8Buf {
+            bytes: self.bytes.to_vec(),
+        }
+    }
+}
+
+impl AsRef<Wtf8> for Wtf8 {
+    #[inline]
+    fn as_ref(&self) -> &Wtf8 {
+        self
+    }
+}
+
+impl AsRef<Wtf8> for str {
+    #[inline]
+    fn as_ref(&self) -> &Wtf8 {
+        Wtf8::new(self)
+    }
+}
+
+impl PartialEq<str> for Wtf8 {
+    #[inline]
+    fn eq(&self, other: &str) -> bool {
+        self.bytes == *other.as_bytes()
+    }
+}
+
+impl fmt::Debug for Wtf8 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("\"")?;
+        for chunk in self.chunks() {
+            match chunk {
+                Ok(s) => {
+                    for ch in s.chars() {
+                        write!(f, "{}", ch.escape_debug())?;
+                    }
+                }
+                Err(unit) => write!(f, "\\u{{{:x}}}", unit)?,
+            }
+        }
+        f.write_str("\"")
+    }
+}
+
+impl fmt::Display for Wtf8 {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.to_string_lossy(), f)
+    }
+}
+
+/// An owned WTF-8 string.
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Wtf8Buf {
+    bytes: Vec<u8>,
+}
+
+impl Wtf8Buf {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            bytes: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Decode potentially ill-formed UTF-16.
+    pub fn from_wide(units: &[u16]) -> Self {
+        let mut bytes = Vec::with_capacity(units.len());
+
+        for res in std::char::decode_utf16(units.iter().copied()) {
+            match res {
+                Ok(ch) => bytes.extend_from_slice(ch.encode_utf8(&mut [0; 4]).as_bytes()),
+                Err(e) => push_surrogate(&mut bytes, e.unpaired_surrogate()),
+            }
+        }
+
+        Self { bytes }
+    }
+
+    /// Returns `None` if `bytes` isn't valid WTF-8 (see [`Wtf8::from_bytes()`]).
+    #[inline]
+    pub fn from_bytes(bytes: Vec<u8>) -> Option<Self> {
+        if is_valid(&bytes) {
+            Some(Self { bytes })
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    pub fn as_wtf8(&self) -> &Wtf8 {
+        self
+    }
+
+    /// Append `s`. If this ends with a high surrogate and `s` starts with a low surrogate, they're
+    /// joined into a single code point.
+    pub fn push(&mut self, s: &Wtf8) {
+        match (self.final_high_surrogate(), s.initial_low_surrogate()) {
+            (Some(high), Some(low)) => {
+                let ch = std::char::decode_utf16([high, low].iter().copied())
+                    .next()
+                    .unwrap()
+                    .unwrap();
+                self.bytes.truncate(self.bytes.len() - 3);
+                self.push_str(ch.encode_utf8(&mut [0; 4]));
+                self.bytes.extend_from_slice(&s.bytes[3..]);
+            }
+
+            _ => self.bytes.extend_from_slice(&s.bytes),
+        }
+    }
+
+    #[inline]
+    pub fn push_str(&mut self, s: &str) {
+        self.bytes.extend_from_slice(s.as_bytes());
+    }
+
+    #[inline]
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    /// Returns the original buffer if there are any unpaired surrogates.
+    #[inline]
+    pub fn into_string(self) -> Result<String, Self> {
+        String::from_utf8(self.bytes).map_err(|e| Self {
+            bytes: e.into_bytes(),
+        })
+    }
+
+    /// Like [`Wtf8::to_os_string()`], but avoids copying where possible.
+    pub fn into_os_string(
+        self,
+        policy: UnpairedPolicy,
+    ) -> Result<OsString, UnpairedSurrogateError> {
+        #[cfg(unix)]
+        if policy == UnpairedPolicy::Wtf8 {
+            return Ok(std::os::unix::ffi::OsStringExt::from_vec(self.bytes));
+        }
+
+        match self.into_string() {
+            Ok(s) => Ok(s.into()),
+            Err(buf) => buf.to_os_string(policy),
+        }
+    }
+}
+
+impl Deref for Wtf8Buf {
+    type Target = Wtf8;
+
+    #[inline]
+    fn deref(&self) -> &Wtf8 {
+        unsafe { Wtf8::from_bytes_unchecked(&self.bytes) }
+    }
+}
+
+impl Borrow<Wtf8> for Wtf8Buf {
+    #[inline]
+    fn borrow(&self) -> &Wtf8 {
+        self
+    }
+}
+
+impl AsRef<Wtf8> for Wtf8Buf {
+    #[inline]
+    fn as_ref(&self) -> &Wtf8 {
+        self
+    }
+}
+
+impl From<&str> for Wtf8Buf {
+    #[inline]
+    fn from(s: &str) -> Self {
+        Wtf8::new(s).to_owned()
+    }
+}
+
+impl From<String> for Wtf8Buf {
+    #[inline]
+    fn from(s: String) -> Self {
+        Self {
+            bytes: s.into_bytes(),
+        }
+    }
+}
+
+impl From<&Wtf8> for Wtf8Buf {
+    #[inline]
+    fn from(s: &Wtf8) -> Self {
+        s.to_owned()
+    }
+}
+
+impl PartialEq<str> for Wtf8Buf {
+    #[inline]
+    fn eq(&self, other: &str) -> bool {
+        **self == *other
+    }
+}
+
+impl PartialEq<&str> for Wtf8Buf {
+    #[inline]
+    fn eq(&self, other: &&str) -> bool {
+        **self == **other
+    }
+}
+
+impl fmt::Debug for Wtf8Buf {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl fmt::Display for Wtf8Buf {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+/// The iterator returned by [`Wtf8::encode_wide()`].
+#[derive(Clone)]
+pub struct EncodeWide<'a> {
+    bytes: std::slice::Iter<'a, u8>,
+    // The second half of a surrogate pair
+    low: Option<u16>,
+}
+
+impl Iterator for EncodeWide<'_> {
+    type Item = u16;
+
+    fn next(&mut self) -> Option<u16> {
+        if let Some(low) = self.low.take() {
+            return Some(low);
+        }
+
+        let b0 = u32::from(*self.bytes.next()?);
+        let mut cont = || u32::from(self.bytes.next().unwrap() & 0x3F);
+
+        // The input is valid, so this is just the usual UTF-8 decoding (surrogates included)
+        let cp = match b0 {
+            0x00..=0x7F => b0,
+            0x80..=0xDF => ((b0 & 0x1F) << 6) | cont(),
+            0xE0..=0xEF => ((b0 & 0x0F) << 12) | (cont() << 6) | cont(),
+            _ => ((b0 & 0x07) << 18) | (cont() << 12) | (cont() << 6) | cont(),
+        };
+
+        if cp >= 0x10000 {
+            let cp = cp - 0x10000;
+            self.low = Some(0xDC00 | (cp & 0x3FF) as u16);
+            Some(0xD800 | (cp >> 10) as u16)
+        } else {
+            Some(cp as u16)
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let low = self.low.is_some() as usize;
+        let len = self.bytes.len();
+        (len.div_ceil(4) + low, Some(len + low))
+    }
+}
+
+impl FusedIterator for EncodeWide<'_> {}
+
+/// The iterator returned by [`Wtf8::find_all()`].
+pub struct Wtf8FindIter {
+    haystack: Vec<u16>,
+    needle: Vec<u16>,
+    state: SearchState,
+}
+
+impl Wtf8FindIter {
+    fn new(haystack: Vec<u16>, needle: Vec<u16>) -> Self {
+        Self {
+            state: SearchState::new(haystack.len(), needle.len()),
+            haystack,
+            needle,
+        }
+    }
+}
+
+impl Iterator for Wtf8FindIter {
+    type Item = usize;
+
+    #[inline]
+    fn next(&mut self) -> Option<usize> {
+        self.state.next(&self.haystack, &self.needle)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.state.size_hint()
+    }
+}
+
+impl DoubleEndedIterator for Wtf8FindIter {
+    #[inline]
+    fn next_back(&mut self) -> Option<usize> {
+        self.state.next_back(&self.haystack, &self.needle)
+    }
+}
+
+impl FusedIterator for Wtf8FindIter {}
+
+/// The iterator returned by [`Wtf8::split()`].
+pub struct Wtf8Split<'a> {
+    rest: Option<&'a [u8]>,
+    sep: &'a [u8],
+    started: bool,
+}
+
+impl<'a> Iterator for Wtf8Split<'a> {
+    type Item = &'a Wtf8;
+
+    fn next(&mut self) -> Option<&'a Wtf8> {
+        let rest = self.rest?;
+
+        let (piece, rest) = if self.sep.is_empty() {
+            // Like str::split(""): an empty piece at each end, and every code point in between
+            if !self.started {
+                self.started = true;
+                (&rest[..0], Some(rest))
+            } else if rest.is_empty() {
+                (rest, None)
+            } else {
+                let len = match rest[0] {
+                    0x00..=0x7F => 1,
+                    0x80..=0xDF => 2,
+                    0xE0..=0xEF => 3,
+                    _ => 4,
+                };
+                (&rest[..len], Some(&rest[len..]))
+            }
+        } else {
+            match find_bytes(rest, self.sep) {
+                Some(index) => (&rest[..index], Some(&rest[index + self.sep.len()..])),
+                None => (rest, None),
+            }
+        };
+
+        self.rest = rest;
+        // A separator can't match in the middle of a code point, so the pieces are still valid
+        Some(unsafe { Wtf8::from_bytes_unchecked(piece) })
+    }
+}
+
+impl FusedIterator for Wtf8Split<'_> {}
+
+// Splits WTF-8 into runs of valid UTF-8 and unpaired surrogates
+struct Chunks<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = Result<&'a str, u16>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.bytes.is_empty() {
+            return None;
+        }
+
+        let valid_len = match std::str::from_utf8(self.bytes) {
+            Ok(s) => s.len(),
+            Err(e) => e.valid_up_to(),
+        };
+
+        if valid_len == 0 {
+            // The input is valid, so this has to be an encoded surrogate
+            let unit = decode_surrogate(self.bytes[1], self.bytes[2]);
+            self.bytes = &self.bytes[3..];
+            Some(Err(unit))
+        } else {
+            let (valid, rest) = self.bytes.split_at(valid_len);
+            self.bytes = rest;
+            Some(Ok(unsafe { std::str::from_utf8_unchecked(valid) }))
+        }
+    }
+}
+
+#[inline]
+fn is_valid(bytes: &[u8]) -> bool {
+    check_wtf8(bytes).is_ok()
+}
+
+// On failure, returns the length of the valid prefix
+pub(crate) fn check_wtf8(bytes: &[u8]) -> Result<(), usize> {
+    let mut pos = 0;
+
+    loop {
+        match std::str::from_utf8(&bytes[pos..]) {
+            Ok(_) => return Ok(()),
+            Err(e) => pos += e.valid_up_to(),
+        }
+
+        // The only thing WTF-8 allows that UTF-8 doesn't is an encoded surrogate
+        match bytes[pos..] {
+            [0xED, b1 @ 0xA0..=0xBF, 0x80..=0xBF, ref tail @ ..] => {
+                // A high surrogate followed by a low surrogate should have been a single code point
+                if b1 < 0xB0 && matches!(tail, [0xED, 0xB0..=0xBF, ..]) {
+                    return Err(pos);
+                }
+                pos += 3;
+            }
+            _ => return Err(pos),
+        }
+    }
+}
+
+// Surrogates get the "generalized UTF-8" 3-byte encoding
+#[inline]
+fn push_surrogate(bytes: &mut Vec<u8>, unit: u16) {
+    bytes.push(0xE0 | (unit >> 12) as u8);
+    bytes.push(0x80 | ((unit >> 6) & 0x3F) as u8);
+    bytes.push(0x80 | (unit & 0x3F) as u8);
+}
+
+#[inline]
+fn decode_surrogate(b1: u8, b2: u8) -> u16 {
+    0xD000 | ((u16::from(b1) & 0x3F) << 6) | (u16::from(b2) & 0x3F)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wide(s: &str) -> Vec<u16> {
+        s.encode_utf16().collect()
+    }
+
+    #[test]
+    fn test_wide_roundtrip() {
+        let cases: &[&[u16]] = &[
+            &[],
+            &[0x61, 0x62],
+            &[0xD83D, 0xDE00],
+            &[0xD800],
+            &[0xDC00, 0xD800],
+            &[0x61, 0xD800, 0x62, 0xDFFF],
+            &[0xD83D, 0xD83D, 0xDE00, 0xDE00],
+        ];
+
+        for &units in cases {
+            let s = Wtf8Buf::from_wide(units);
+            assert_eq!(s.encode_wide().collect::<Vec<_>>(), units);
+            assert_eq!(Wtf8::from_bytes(s.as_bytes()), Some(&*s));
+        }
+
+        let s = Wtf8Buf::from_wide(&[0x61, 0xD800]);
+        assert_eq!(s.as_bytes(), b"a\xed\xa0\x80");
+        assert_eq!(s.as_str(), None);
+        assert_eq!(s.to_string_lossy(), "a\u{fffd}");
+        assert_eq!(format!("{:?}", s), "\"a\\u{d800}\"");
+
+        let s = Wtf8Buf::from_wide(&wide("caf\u{e9} \u{1f600}"));
+        assert_eq!(s, "caf\u{e9} \u{1f600}");
+        assert_eq!(s.as_str(), Some("caf\u{e9} \u{1f600}"));
+    }
+
+    #[test]
+    fn test_from_bytes() {
+        assert!(Wtf8::from_bytes(b"abc").is_some());
+        assert!(Wtf8::from_bytes(b"\xed\xa0\x80").is_some());
+        assert!(Wtf8::from_bytes(b"\xed\xb0\x80\xed\xa0\x80").is_some());
+
+        assert!(Wtf8::from_bytes(b"\xff").is_none());
+        assert!(Wtf8::from_bytes(b"\xed\xa0").is_none());
+        // Should have been encoded as a pair
+        assert!(Wtf8::from_bytes(b"\xed\xa0\xbd\xed\xb8\x80").is_none());
+    }
+
+    #[test]
+    fn test_push() {
+        let high = Wtf8Buf::from_wide(&[0x61, 0xD83D]);
+        let low = Wtf8Buf::from_wide(&[0xDE00, 0x62]);
+
+        let mut s = high.clone();
+        s.push(&low);
+        assert_eq!(s, "a\u{1f600}b");
+
+        let mut s = low;
+        s.push(&high);
+        assert_eq!(
+            s.encode_wide().collect::<Vec<_>>(),
+            [0xDE00, 0x62, 0x61, 0xD83D]
+        );
+
+        s.push_str("c");
+        assert_eq!(s.into_string().unwrap_err().as_bytes().last(), Some(&b'c'));
+    }
+
+    #[test]
+    fn test_search() {
+        // "a" U+1F600 "b" <lone high surrogate> "a"
+        let s = Wtf8Buf::from_wide(&[0x61, 0xD83D, 0xDE00, 0x62, 0xD83D, 0x61]);
+        let high = Wtf8Buf::from_wide(&[0xD83D]);
+
+        assert!(s.starts_with("a".as_ref()));
+        assert!(!s.starts_with("b".as_ref()));
+        assert!(s.ends_with(Wtf8Buf::from_wide(&[0xD83D, 0x61]).as_ref()));
+        assert!(s.starts_with("".as_ref()));
+
+        assert_eq!(s.find("a".as_ref()), Some(0));
+        assert_eq!(s.rfind("a".as_ref()), Some(5));
+        assert_eq!(s.find("b".as_ref()), Some(3));
+        assert_eq!(s.find("c".as_ref()), None);
+        assert_eq!(s.rfind("".as_ref()), Some(6));
+
+        // Offsets are in code units, so the high surrogate matches inside the pair
+        assert_eq!(s.find_all(&high).collect::<Vec<_>>(), [1, 4]);
+        assert_eq!(s.find_all(&high).rev().collect::<Vec<_>>(), [4, 1]);
+        assert_eq!(
+            Wtf8::new("aaa").find_all("aa".as_ref()).collect::<Vec<_>>(),
+            [0, 1]
+        );
+        assert_eq!(
+            Wtf8::new("ab").find_all("".as_ref()).collect::<Vec<_>>(),
+            [0, 1, 2]
+        );
+
+        assert_eq!(s.substr(0, 1), "a");
+        assert_eq!(s.substr(1, 4), "\u{1f600}b");
+        assert_eq!(s.substr(2, 3).encode_wide().collect::<Vec<_>>(), [0xDE00]);
+    }
+
+    #[test]
+    fn test_split_strip() {
+        let s = Wtf8Buf::from_wide(&[0x61, 0x2F, 0xD800, 0x2F, 0x62]);
+        let sep = Wtf8::new("/");
+
+        let pieces: Vec<&Wtf8> = s.split(sep).collect();
+        assert_eq!(pieces.len(), 3);
+        assert_eq!(pieces[0], "a");
+        assert_eq!(pieces[1].encode_wide().collect::<Vec<_>>(), [0xD800]);
+        assert_eq!(pieces[2], "b");
+
+        let (head, tail) = s.split_once(sep).unwrap();
+        assert_eq!(head, "a");
+        assert_eq!(tail.strip_suffix("/b".as_ref()), Some(pieces[1]));
+        assert_eq!(s.strip_prefix("a/".as_ref()), Some(tail));
+        assert_eq!(s.strip_prefix("b".as_ref()), None);
+
+        assert_eq!(
+            Wtf8::new("a\u{e9}").split("".as_ref()).collect::<Vec<_>>(),
+            ["", "a", "\u{e9}", ""]
+                .iter()
+                .map(Wtf8::new)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_to_os_string() {
+        let s = Wtf8Buf::from_wide(&[0x61, 0x62, 0xDC00, 0x63]);
+
+        assert_eq!(
+            s.to_os_string(UnpairedPolicy::Replace).unwrap(),
+            "ab\u{fffd}c"
+        );
+
+        let err = s.to_os_string(UnpairedPolicy::Error).unwrap_err();
+        assert_eq!(err.unpaired_surrogate(), 0xDC00);
+        assert_eq!(err.index(), 2);
+        assert_eq!(err.to_string(), "unpaired surrogate 0xdc00 at index 2");
+
+        let os = s.to_os_string(UnpairedPolicy::Wtf8).unwrap();
+        assert_eq!(Wtf8::from_os_str(&os), Some(&*s));
+        assert_eq!(s.clone().into_os_string(UnpairedPolicy::Wtf8).unwrap(), os);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            assert_eq!(os.as_bytes(), b"ab\xed\xb0\x80c");
+            assert_eq!(Wtf8::from_os_str(OsStr::from_bytes(b"\xff")), None);
+        }
+
+        for policy in [
+            UnpairedPolicy::Replace,
+            UnpairedPolicy::Wtf8,
+            UnpairedPolicy::Error,
+        ] {
+            assert_eq!(Wtf8Buf::from("abc").into_os_string(policy).unwrap(), "abc");
+        }
+    }
+}